@@ -0,0 +1,116 @@
+// Runtime library linked into every compiled program. The compiler declares
+// these functions in each module, see `RUNTIME_FUNCTIONS` in src/builtins.rs.
+
+#define _POSIX_C_SOURCE 200809L
+
+#include <inttypes.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+// Layout of the `str` type, `{ i8*, i64 }` in LLVM. It is only written
+// through a pointer, a `str` argument is passed as its two fields.
+typedef struct {
+    const char *ptr;
+    int64_t len;
+} ac_str;
+
+#define DEFINE_PRINT(suffix, params, format, ...)         \
+    void __ac_print_##suffix params {                    \
+        printf(format, __VA_ARGS__);                     \
+    }                                                    \
+    void __ac_println_##suffix params {                  \
+        printf(format "\n", __VA_ARGS__);                \
+    }
+
+DEFINE_PRINT(i64, (int64_t value), "%" PRId64, value)
+DEFINE_PRINT(u64, (uint64_t value), "%" PRIu64, value)
+DEFINE_PRINT(f64, (double value), "%g", value)
+DEFINE_PRINT(bool, (int64_t value), "%s", value ? "true" : "false")
+
+// Written with `fwrite`, as `%.*s` would stop at a `\0` inside the string.
+void __ac_print_str(const char *ptr, int64_t len) {
+    fwrite(ptr, 1, len, stdout);
+}
+
+void __ac_println_str(const char *ptr, int64_t len) {
+    fwrite(ptr, 1, len, stdout);
+    putchar('\n');
+}
+
+// Writes a `char`, which is a Unicode scalar value, encoded as UTF-8.
+static void put_utf8(uint32_t c) {
+    if (c < 0x80) {
+        putchar(c);
+    } else if (c < 0x800) {
+        putchar(0xC0 | c >> 6);
+        putchar(0x80 | (c & 0x3F));
+    } else if (c < 0x10000) {
+        putchar(0xE0 | c >> 12);
+        putchar(0x80 | (c >> 6 & 0x3F));
+        putchar(0x80 | (c & 0x3F));
+    } else {
+        putchar(0xF0 | c >> 18);
+        putchar(0x80 | (c >> 12 & 0x3F));
+        putchar(0x80 | (c >> 6 & 0x3F));
+        putchar(0x80 | (c & 0x3F));
+    }
+}
+
+void __ac_print_char(uint32_t value) {
+    put_utf8(value);
+}
+
+void __ac_println_char(uint32_t value) {
+    put_utf8(value);
+    putchar('\n');
+}
+
+int64_t __ac_read_int(void) {
+    int64_t value = 0;
+
+    if (scanf("%" SCNd64, &value) != 1) {
+        fprintf(stderr, "read_int: expected an integer\n");
+        exit(1);
+    }
+
+    // drop the rest of the line, so a following `read_line` starts fresh
+    int c;
+    while ((c = getchar()) != '\n' && c != EOF) {
+    }
+
+    return value;
+}
+
+// Reads a line without its trailing newline into `out`. The string is never
+// freed.
+void __ac_read_line(ac_str *out) {
+    char *line = NULL;
+    size_t capacity = 0;
+    ssize_t len = getline(&line, &capacity, stdin);
+
+    if (len < 0) {
+        free(line);
+        *out = (ac_str){"", 0};
+        return;
+    }
+
+    if (len > 0 && line[len - 1] == '\n') {
+        line[--len] = '\0';
+    }
+
+    *out = (ac_str){line, len};
+}
+
+// Called by a failed bounds check, it reports where the program indexed out
+// of bounds and exits with the status Rust uses for panics.
+void __ac_index_out_of_bounds(const char *file, int64_t file_len, int64_t line,
+                              int64_t column, int64_t index, int64_t len) {
+    fflush(stdout);
+    fprintf(stderr,
+            "panic at %.*s:%" PRId64 ":%" PRId64
+            ": index out of bounds: the len is %" PRId64
+            " but the index is %" PRId64 "\n",
+            (int)file_len, file, line, column, len, index);
+    exit(101);
+}
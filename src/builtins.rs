@@ -0,0 +1,137 @@
+use crate::internal_representations::ast::{IntType, Type};
+
+/// Functions every program can call without defining them. Calls to them are
+/// lowered to calls of the runtime library in `runtime/runtime.c`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Builtin {
+    Print,
+    Println,
+    ReadInt,
+    ReadLine,
+    Len,
+    Alloc,
+    Free,
+}
+
+impl Builtin {
+    pub fn from_name(name: &str) -> Option<Builtin> {
+        Some(match name {
+            "print" => Builtin::Print,
+            "println" => Builtin::Println,
+            "read_int" => Builtin::ReadInt,
+            "read_line" => Builtin::ReadLine,
+            "len" => Builtin::Len,
+            "alloc" => Builtin::Alloc,
+            "free" => Builtin::Free,
+            _ => return None,
+        })
+    }
+
+    pub fn arity(self) -> usize {
+        match self {
+            Builtin::Print | Builtin::Println | Builtin::Len | Builtin::Alloc | Builtin::Free => 1,
+            Builtin::ReadInt | Builtin::ReadLine => 0,
+        }
+    }
+}
+
+/// Function implemented by the runtime library and declared in every module.
+/// A `str` parameter is passed as two parameters, the pointer to its bytes
+/// and its length, and a `str` is returned through a pointer passed before
+/// the other arguments, so no aggregate crosses the C boundary by value.
+pub struct RuntimeFunction {
+    pub name: &'static str,
+    pub params: &'static [Type],
+    pub ret: Type,
+}
+
+const U64: Type = Type::Int(IntType::U64);
+
+pub const RUNTIME_FUNCTIONS: &[RuntimeFunction] = &[
+    RuntimeFunction {
+        name: "__ac_print_i64",
+        params: &[Type::I64],
+        ret: Type::Unit,
+    },
+    RuntimeFunction {
+        name: "__ac_print_u64",
+        params: &[U64],
+        ret: Type::Unit,
+    },
+    RuntimeFunction {
+        name: "__ac_print_f64",
+        params: &[Type::F64],
+        ret: Type::Unit,
+    },
+    RuntimeFunction {
+        name: "__ac_print_bool",
+        params: &[Type::I64],
+        ret: Type::Unit,
+    },
+    RuntimeFunction {
+        name: "__ac_print_char",
+        params: &[Type::Char],
+        ret: Type::Unit,
+    },
+    RuntimeFunction {
+        name: "__ac_print_str",
+        params: &[Type::Str],
+        ret: Type::Unit,
+    },
+    RuntimeFunction {
+        name: "__ac_println_i64",
+        params: &[Type::I64],
+        ret: Type::Unit,
+    },
+    RuntimeFunction {
+        name: "__ac_println_u64",
+        params: &[U64],
+        ret: Type::Unit,
+    },
+    RuntimeFunction {
+        name: "__ac_println_f64",
+        params: &[Type::F64],
+        ret: Type::Unit,
+    },
+    RuntimeFunction {
+        name: "__ac_println_bool",
+        params: &[Type::I64],
+        ret: Type::Unit,
+    },
+    RuntimeFunction {
+        name: "__ac_println_char",
+        params: &[Type::Char],
+        ret: Type::Unit,
+    },
+    RuntimeFunction {
+        name: "__ac_println_str",
+        params: &[Type::Str],
+        ret: Type::Unit,
+    },
+    RuntimeFunction {
+        name: "__ac_read_int",
+        params: &[],
+        ret: Type::I64,
+    },
+    RuntimeFunction {
+        name: "__ac_read_line",
+        params: &[],
+        ret: Type::Str,
+    },
+    // file, line and column of the indexing expression, the index and the
+    // length; it never returns
+    RuntimeFunction {
+        name: "__ac_index_out_of_bounds",
+        params: &[Type::Str, Type::I64, Type::I64, Type::I64, Type::I64],
+        ret: Type::Unit,
+    },
+];
+
+pub fn runtime_function(name: &str) -> Option<&'static RuntimeFunction> {
+    RUNTIME_FUNCTIONS
+        .iter()
+        .find(|function| function.name == name)
+}
+
+/// Source of the runtime library, compiled and linked into every executable.
+pub const RUNTIME_SOURCE: &str = include_str!("../runtime/runtime.c");
@@ -1,25 +1,99 @@
 use std::collections::HashMap;
 
 use inkwell::{
+    basic_block::BasicBlock,
     builder::Builder,
     context::Context,
-    module::Module,
+    module::{Linkage, Module},
     passes::PassManager,
-    types::BasicMetadataTypeEnum,
-    values::{BasicMetadataValueEnum, FunctionValue, IntValue, PointerValue},
-    IntPredicate,
+    types::{
+        BasicMetadataTypeEnum, BasicType, BasicTypeEnum, FloatType, FunctionType, IntType,
+        StructType,
+    },
+    values::{
+        ArrayValue, BasicMetadataValueEnum, BasicValueEnum, FunctionValue, IntValue, PointerValue,
+        StructValue,
+    },
+    AddressSpace, FloatPredicate, IntPredicate,
 };
 
-use crate::internal_representations::gast::{Expr, Function, Operator, Program, Stmt};
+use crate::{
+    builtins::{runtime_function, RUNTIME_FUNCTIONS},
+    diagnostics::{SourceFile, Span},
+    error::CompileError,
+    internal_representations::ast::{
+        self, Arm, BindingId, Expr, ExprKind, ExternFunction, Function, LogicalOperator, Operator,
+        Pattern, PatternKind, Program, Stmt, StmtKind, Type, UnaryOperator,
+    },
+};
+
+/// Blocks the innermost loop jumps to on `continue` and `break`.
+struct LoopTarget<'ctx> {
+    latch: BasicBlock<'ctx>,
+    exit: BasicBlock<'ctx>,
+    /// Whether any `break` leaves the loop, so the exit block is reachable.
+    broken: bool,
+}
 
 pub struct Compiler<'a, 'ctx> {
     pub context: &'ctx Context,
     pub builder: &'a Builder<'ctx>,
     pub fpm: &'a PassManager<FunctionValue<'ctx>>,
     pub module: &'a Module<'ctx>,
+    pub program: &'a Program,
+    /// File the program was read from, for the location of runtime errors.
+    pub source: &'a SourceFile,
+    /// Whether indexing checks that the index is in bounds.
+    pub bounds_checks: bool,
 
-    variables: HashMap<String, PointerValue<'ctx>>,
+    variables: HashMap<BindingId, PointerValue<'ctx>>,
+    /// LLVM globals of the statics.
+    globals: HashMap<BindingId, PointerValue<'ctx>>,
+    loops: Vec<LoopTarget<'ctx>>,
     fn_value_opt: Option<FunctionValue<'ctx>>,
+    /// Where the current function stores the struct or enum it returns, see
+    /// `make_prototype`.
+    sret: Option<PointerValue<'ctx>>,
+    /// Name of the source file as a `str`, created on first use.
+    source_name: Option<BasicValueEnum<'ctx>>,
+}
+
+type CompileResult<T> = Result<T, CompileError>;
+
+fn enum_name(ty: &Type) -> &str {
+    match ty {
+        Type::Enum(name) => name,
+        _ => unreachable!("{} is not an enum", ty),
+    }
+}
+
+/// The variant or the literal value a `match` switches on to reach the arm,
+/// `None` for patterns matching any value.
+fn pattern_head(pattern: &Pattern) -> Option<u64> {
+    match &pattern.kind {
+        PatternKind::Wildcard | PatternKind::Binding(_) => None,
+        PatternKind::Variant(index, _) => Some(*index as u64),
+        PatternKind::Literal(literal) => Some(match literal.kind {
+            ExprKind::IntLiteral(bits) => bits,
+            ExprKind::BoolLiteral(boolean) => boolean as u64,
+            ExprKind::CharLiteral(char) => char as u64,
+            _ => unreachable!("{:?} is not a literal pattern", literal),
+        }),
+    }
+}
+
+/// Size and alignment of a struct with fields of the given size and
+/// alignment, each one placed at the next offset suiting its alignment.
+fn struct_layout(fields: impl IntoIterator<Item = (u64, u64)>) -> (u64, u64) {
+    let (size, align) = fields.into_iter().fold(
+        (0u64, 1u64),
+        |(offset, align), (field_size, field_align)| {
+            let offset = offset.next_multiple_of(field_align);
+            (offset + field_size, align.max(field_align))
+        },
+    );
+
+    (size.next_multiple_of(align), align)
 }
 
 impl<'a, 'ctx> Compiler<'a, 'ctx> {
@@ -31,7 +105,257 @@ impl<'a, 'ctx> Compiler<'a, 'ctx> {
         self.fn_value_opt.unwrap()
     }
 
-    fn create_entry_block_alloca(&self, name: &str) -> PointerValue<'ctx> {
+    /// Allocates the stack slot of a binding in the entry block of the current
+    /// function.
+    fn declare_variable(&mut self, id: BindingId) -> PointerValue<'ctx> {
+        let binding = self.program.binding(id);
+        let alloca = self.create_entry_block_alloca(&binding.name, self.basic_type(&binding.ty));
+        self.variables.insert(id, alloca);
+        alloca
+    }
+
+    fn get_variable(&self, id: BindingId) -> PointerValue<'ctx> {
+        match self.variables.get(&id) {
+            Some(variable) => *variable,
+            None => self.globals[&id],
+        }
+    }
+
+    fn compile_block(&mut self, block: &[Stmt]) -> CompileResult<()> {
+        for stmt in block {
+            // statements after a `return` can never run
+            if self.is_terminated() {
+                break;
+            }
+            self.compile_stmt(stmt)?;
+        }
+        Ok(())
+    }
+
+    /// Whether the current block already ends with a terminator, so nothing
+    /// may be appended to it anymore.
+    fn is_terminated(&self) -> bool {
+        self.builder
+            .get_insert_block()
+            .and_then(|block| block.get_terminator())
+            .is_some()
+    }
+
+    /// Falls through to `target`, unless the current block already left the
+    /// function or jumped elsewhere.
+    fn branch_to(&self, target: BasicBlock<'ctx>) {
+        if !self.is_terminated() {
+            self.builder.build_unconditional_branch(target);
+        }
+    }
+
+    fn int_type(&self, ty: ast::IntType) -> IntType<'ctx> {
+        self.context.custom_width_int_type(ty.bits())
+    }
+
+    fn float_type(&self, ty: ast::FloatType) -> FloatType<'ctx> {
+        match ty {
+            ast::FloatType::F32 => self.context.f32_type(),
+            ast::FloatType::F64 => self.context.f64_type(),
+        }
+    }
+
+    /// Named struct `{ i8*, i64 }` holding the pointer to the bytes of a
+    /// string and its length.
+    fn str_type(&self) -> StructType<'ctx> {
+        self.module.get_struct_type("str").unwrap_or_else(|| {
+            let str_type = self.context.opaque_struct_type("str");
+            str_type.set_body(
+                &[
+                    self.context
+                        .i8_type()
+                        .ptr_type(AddressSpace::default())
+                        .into(),
+                    self.context.i64_type().into(),
+                ],
+                false,
+            );
+            str_type
+        })
+    }
+
+    /// Constant `str` of the given string. The bytes live in a private
+    /// global and are followed by a NUL that the length does not count, so
+    /// the pointer can be handed to C and interior NULs are kept.
+    fn const_str(&self, string: &str) -> StructValue<'ctx> {
+        let mut bytes = string.as_bytes().to_vec();
+        bytes.push(0);
+        let bytes = self.context.const_string(&bytes, false);
+
+        let global = self.module.add_global(bytes.get_type(), None, "str");
+        global.set_initializer(&bytes);
+        global.set_constant(true);
+        global.set_linkage(Linkage::Private);
+
+        let ptr = global
+            .as_pointer_value()
+            .const_cast(self.context.i8_type().ptr_type(AddressSpace::default()));
+        let len = self
+            .context
+            .i64_type()
+            .const_int(string.len() as u64, false);
+
+        self.str_type()
+            .const_named_struct(&[ptr.into(), len.into()])
+    }
+
+    /// Pointer to the bytes of a string and its length.
+    fn str_fields(&self, value: BasicValueEnum<'ctx>) -> (PointerValue<'ctx>, IntValue<'ctx>) {
+        let value = value.into_struct_value();
+        let ptr = self
+            .builder
+            .build_extract_value(value, 0, "strptr")
+            .expect("str has a pointer field")
+            .into_pointer_value();
+        let len = self
+            .builder
+            .build_extract_value(value, 1, "strlen")
+            .expect("str has a length field")
+            .into_int_value();
+        (ptr, len)
+    }
+
+    /// Named struct `%struct.Name` of a struct declared in the program.
+    fn struct_type(&self, name: &str) -> StructType<'ctx> {
+        self.module
+            .get_struct_type(&format!("struct.{}", name))
+            .expect("structs are declared before any function")
+    }
+
+    /// Named struct `%enum.Name` of an enum declared in the program. It holds
+    /// the index of the variant as an `i32` tag, followed by enough `i64`
+    /// words to store the fields of any variant, see `variant_type`.
+    fn enum_type(&self, name: &str) -> StructType<'ctx> {
+        self.module
+            .get_struct_type(&format!("enum.{}", name))
+            .expect("enums are declared before any function")
+    }
+
+    /// Struct of the fields of a variant, which is how they are laid out in
+    /// the words following the tag.
+    fn variant_type(&self, name: &str, index: usize) -> StructType<'ctx> {
+        let def = self.program.enum_def(name).expect("enum is declared");
+        let fields: Vec<_> = def.variants[index]
+            .fields
+            .iter()
+            .map(|ty| self.basic_type(ty))
+            .collect();
+        self.context.struct_type(&fields, false)
+    }
+
+    /// Size and alignment of a value in bytes. Every type is assumed to be
+    /// aligned to its size, up to 8 bytes, so the result is never smaller
+    /// than the real layout of the target.
+    fn layout(&self, ty: &Type) -> (u64, u64) {
+        match ty {
+            Type::Int(int) => (int.bits() as u64 / 8, int.bits() as u64 / 8),
+            Type::Float(ast::FloatType::F32) | Type::Char => (4, 4),
+            Type::Float(ast::FloatType::F64) => (8, 8),
+            Type::Bool => (1, 1),
+            Type::Str | Type::Slice(_) => (16, 8),
+            Type::Pointer(_) => (8, 8),
+            Type::Unit => (0, 1),
+            Type::Struct(name) => {
+                let def = self.program.struct_def(name).expect("struct is declared");
+                struct_layout(def.fields.iter().map(|field| self.layout(&field.ty)))
+            }
+            Type::Enum(name) => struct_layout([(4, 4), (self.payload_words(name) * 8, 8)]),
+            Type::Array(element, len) => {
+                let (size, align) = self.layout(element);
+                (size * len, align)
+            }
+        }
+    }
+
+    /// Number of `i64` words needed for the fields of the largest variant.
+    fn payload_words(&self, name: &str) -> u64 {
+        let def = self.program.enum_def(name).expect("enum is declared");
+
+        def.variants
+            .iter()
+            .map(|variant| {
+                let (size, _) = struct_layout(variant.fields.iter().map(|ty| self.layout(ty)));
+                size.div_ceil(8)
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Address of a field of a variant, in the enum stored at `ptr`.
+    fn payload_field(
+        &self,
+        ptr: PointerValue<'ctx>,
+        name: &str,
+        index: usize,
+        field: usize,
+    ) -> PointerValue<'ctx> {
+        let payload = self
+            .builder
+            .build_struct_gep(ptr, 1, "payload")
+            .expect("enums have a payload");
+        let variant_ptr = self
+            .variant_type(name, index)
+            .ptr_type(AddressSpace::default());
+        let variant = self
+            .builder
+            .build_pointer_cast(payload, variant_ptr, "variant");
+
+        self.builder
+            .build_struct_gep(variant, field as u32, "field")
+            .expect("field index is in bounds")
+    }
+
+    /// LLVM type of a value of the given type. `unit` is an empty struct, so it
+    /// can be stored and passed around like any other value.
+    fn basic_type(&self, ty: &Type) -> BasicTypeEnum<'ctx> {
+        match ty {
+            Type::Int(int) => self.int_type(*int).into(),
+            Type::Bool => self.context.bool_type().into(),
+            Type::Char => self.context.i32_type().into(),
+            Type::Float(float) => self.float_type(*float).into(),
+            Type::Str => self.str_type().into(),
+            Type::Unit => self.context.struct_type(&[], false).into(),
+            Type::Struct(name) => self.struct_type(name).into(),
+            Type::Enum(name) => self.enum_type(name).into(),
+            Type::Array(element, len) => self.basic_type(element).array_type(*len as u32).into(),
+            Type::Slice(element) => self.slice_type(element).into(),
+            Type::Pointer(pointee) => self
+                .basic_type(pointee)
+                .ptr_type(AddressSpace::default())
+                .into(),
+        }
+    }
+
+    /// Struct `{ T*, i64 }` of a slice, the pointer to the first element and
+    /// the number of elements.
+    fn slice_type(&self, element: &Type) -> StructType<'ctx> {
+        let ptr = self.basic_type(element).ptr_type(AddressSpace::default());
+        self.context
+            .struct_type(&[ptr.into(), self.context.i64_type().into()], false)
+    }
+
+    /// Type of a parameter, which is a pointer for structs and enums, see
+    /// `make_prototype`.
+    fn param_type(&self, ty: &Type) -> BasicTypeEnum<'ctx> {
+        let basic_type = self.basic_type(ty);
+
+        if ty.is_aggregate() {
+            basic_type.ptr_type(AddressSpace::default()).into()
+        } else {
+            basic_type
+        }
+    }
+
+    fn unit_value(&self) -> BasicValueEnum<'ctx> {
+        self.context.const_struct(&[], false).into()
+    }
+
+    fn create_entry_block_alloca(&self, name: &str, ty: BasicTypeEnum<'ctx>) -> PointerValue<'ctx> {
         let builder = self.context.create_builder();
 
         let entry = self.fn_value().get_first_basic_block().unwrap();
@@ -41,38 +365,460 @@ impl<'a, 'ctx> Compiler<'a, 'ctx> {
             None => builder.position_at_end(entry),
         }
 
-        builder.build_alloca(self.context.i64_type(), name)
+        builder.build_alloca(ty, name)
+    }
+
+    /// Address of a place, see `ExprKind::is_place`. Other values are copied
+    /// to the stack first, so a field of any struct is read the same way.
+    fn compile_place(&mut self, expr: &Expr) -> CompileResult<PointerValue<'ctx>> {
+        match &expr.kind {
+            ExprKind::Variable(id) => Ok(self.get_variable(*id)),
+            ExprKind::Field(base, index) => {
+                let base = self.compile_place(base)?;
+                Ok(self
+                    .builder
+                    .build_struct_gep(base, *index as u32, "field")
+                    .expect("field index is in bounds"))
+            }
+            ExprKind::Deref(pointer) => Ok(self.compile_expr(pointer)?.into_pointer_value()),
+            ExprKind::Index(base, index) => {
+                let i64_type = self.context.i64_type();
+
+                let (elements, len) = match &base.ty {
+                    Type::Array(_, len) => {
+                        let array = self.compile_place(base)?;
+                        let first = self.first_element(array);
+                        (first, i64_type.const_int(*len, false))
+                    }
+                    _ => {
+                        let slice = self.compile_expr(base)?.into_struct_value();
+                        let elements = self
+                            .builder
+                            .build_extract_value(slice, 0, "elements")
+                            .expect("slices have a pointer field")
+                            .into_pointer_value();
+                        let len = self
+                            .builder
+                            .build_extract_value(slice, 1, "len")
+                            .expect("slices have a length field")
+                            .into_int_value();
+                        (elements, len)
+                    }
+                };
+
+                let index = self.compile_expr(index)?.into_int_value();
+                self.build_bounds_check(index, len, expr.span);
+
+                // SAFETY: without bounds checks an index out of bounds is
+                // undefined behavior, like in C
+                Ok(unsafe {
+                    self.builder
+                        .build_in_bounds_gep(elements, &[index], "element")
+                })
+            }
+            _ => {
+                let value = self.compile_expr(expr)?;
+                let slot = self.create_entry_block_alloca("tmp", self.basic_type(&expr.ty));
+                self.builder.build_store(slot, value);
+                Ok(slot)
+            }
+        }
+    }
+
+    /// Pointer to the first element of the array at `array`.
+    fn first_element(&self, array: PointerValue<'ctx>) -> PointerValue<'ctx> {
+        let zero = self.context.i64_type().const_zero();
+
+        // SAFETY: the first element of an array is in bounds, and an empty
+        // array is never read through the pointer
+        unsafe {
+            self.builder
+                .build_in_bounds_gep(array, &[zero, zero], "elements")
+        }
     }
 
-    fn compile_expr(&mut self, expr: &Expr) -> Option<IntValue<'ctx>> {
-        match expr {
-            Expr::IntLiteral(int) => Some(self.context.i64_type().const_int(*int as u64, true)),
-            Expr::StringLiteral(_) => todo!(),
-            Expr::Name(name) => Some(
+    /// Name of the source file as a `str` constant, for runtime errors.
+    fn source_name(&mut self) -> BasicValueEnum<'ctx> {
+        if let Some(name) = self.source_name {
+            return name;
+        }
+
+        let name = self.const_str(&self.source.name).into();
+
+        self.source_name = Some(name);
+        name
+    }
+
+    /// Calls the runtime panic handler with the location of `span` unless
+    /// `index < len`. The comparison is unsigned, so negative indices fail
+    /// as well.
+    fn build_bounds_check(&mut self, index: IntValue<'ctx>, len: IntValue<'ctx>, span: Span) {
+        if !self.bounds_checks {
+            return;
+        }
+
+        let parent = self.fn_value();
+        let fail_bb = self.context.append_basic_block(parent, "outofbounds");
+        let ok_bb = self.context.append_basic_block(parent, "inbounds");
+
+        let in_bounds = self
+            .builder
+            .build_int_compare(IntPredicate::ULT, index, len, "inbounds");
+        self.builder
+            .build_conditional_branch(in_bounds, ok_bb, fail_bb);
+
+        self.builder.position_at_end(fail_bb);
+        let handler = self
+            .get_function("__ac_index_out_of_bounds")
+            .expect("runtime is declared before any function");
+        let source_name = self.source_name();
+        let (file, file_len) = self.str_fields(source_name);
+        let (line, column) = self.source.line_col(span.start);
+        let i64_type = self.context.i64_type();
+        let args: [BasicMetadataValueEnum; 6] = [
+            file.into(),
+            file_len.into(),
+            i64_type.const_int(line as u64, false).into(),
+            i64_type.const_int(column as u64, false).into(),
+            index.into(),
+            len.into(),
+        ];
+        self.builder.build_call(handler, &args, "");
+        self.builder.build_unreachable();
+
+        self.builder.position_at_end(ok_bb);
+    }
+
+    /// Evaluates the operands of a binary operator and emits the instruction
+    /// matching the type of the operands.
+    fn compile_binary(
+        &mut self,
+        op: &Operator,
+        lhs: &Expr,
+        rhs: &Expr,
+    ) -> CompileResult<BasicValueEnum<'ctx>> {
+        let ty = lhs.ty.clone();
+        let lhs = self.compile_expr(lhs)?;
+        let rhs = self.compile_expr(rhs)?;
+
+        if ty.is_float() {
+            let (lhs, rhs) = (lhs.into_float_value(), rhs.into_float_value());
+
+            let predicate = match op {
+                Operator::Add => {
+                    return Ok(self.builder.build_float_add(lhs, rhs, "addtmp").into())
+                }
+                Operator::Sub => {
+                    return Ok(self.builder.build_float_sub(lhs, rhs, "subtmp").into())
+                }
+                Operator::Mul => {
+                    return Ok(self.builder.build_float_mul(lhs, rhs, "multmp").into())
+                }
+                Operator::Div => {
+                    return Ok(self.builder.build_float_div(lhs, rhs, "divtmp").into())
+                }
+                Operator::Eq => FloatPredicate::OEQ,
+                Operator::Ne => FloatPredicate::UNE,
+                Operator::Lt => FloatPredicate::OLT,
+                Operator::Le => FloatPredicate::OLE,
+                Operator::Gt => FloatPredicate::OGT,
+                Operator::Ge => FloatPredicate::OGE,
+            };
+
+            return Ok(self
+                .builder
+                .build_float_compare(predicate, lhs, rhs, "cmptmp")
+                .into());
+        }
+
+        // booleans and pointers only support `==` and `!=`, which are the
+        // same for both, pointers compare their addresses
+        let signed = match ty {
+            Type::Int(int) => int.is_signed(),
+            _ => false,
+        };
+        let (lhs, rhs) = match ty {
+            Type::Pointer(_) => {
+                let i64_type = self.context.i64_type();
+                (
+                    self.builder
+                        .build_ptr_to_int(lhs.into_pointer_value(), i64_type, "addrtmp"),
+                    self.builder
+                        .build_ptr_to_int(rhs.into_pointer_value(), i64_type, "addrtmp"),
+                )
+            }
+            _ => (lhs.into_int_value(), rhs.into_int_value()),
+        };
+
+        let predicate = match (op, signed) {
+            (Operator::Add, _) => return Ok(self.builder.build_int_add(lhs, rhs, "addtmp").into()),
+            (Operator::Sub, _) => return Ok(self.builder.build_int_sub(lhs, rhs, "subtmp").into()),
+            (Operator::Mul, _) => return Ok(self.builder.build_int_mul(lhs, rhs, "multmp").into()),
+            (Operator::Div, true) => {
+                return Ok(self.builder.build_int_signed_div(lhs, rhs, "divtmp").into())
+            }
+            (Operator::Div, false) => {
+                return Ok(self
+                    .builder
+                    .build_int_unsigned_div(lhs, rhs, "divtmp")
+                    .into())
+            }
+            (Operator::Eq, _) => IntPredicate::EQ,
+            (Operator::Ne, _) => IntPredicate::NE,
+            (Operator::Lt, true) => IntPredicate::SLT,
+            (Operator::Le, true) => IntPredicate::SLE,
+            (Operator::Gt, true) => IntPredicate::SGT,
+            (Operator::Ge, true) => IntPredicate::SGE,
+            (Operator::Lt, false) => IntPredicate::ULT,
+            (Operator::Le, false) => IntPredicate::ULE,
+            (Operator::Gt, false) => IntPredicate::UGT,
+            (Operator::Ge, false) => IntPredicate::UGE,
+        };
+
+        Ok(self
+            .builder
+            .build_int_compare(predicate, lhs, rhs, "cmptmp")
+            .into())
+    }
+
+    /// Converts a value between the types the type checker accepts in an `as`
+    /// cast.
+    fn compile_cast(
+        &self,
+        value: BasicValueEnum<'ctx>,
+        from: &Type,
+        to: &Type,
+    ) -> BasicValueEnum<'ctx> {
+        // a `char` converts like the `u32` of its code point
+        let as_int = |ty: &Type| match ty {
+            Type::Char => Type::Int(ast::IntType::U32),
+            ty => ty.clone(),
+        };
+
+        match (&as_int(from), &as_int(to)) {
+            (Type::Int(from), Type::Int(to)) => {
+                let value = value.into_int_value();
+                let target = self.int_type(*to);
+
+                if to.bits() < from.bits() {
+                    self.builder
+                        .build_int_truncate(value, target, "casttmp")
+                        .into()
+                } else if to.bits() == from.bits() {
+                    value.into()
+                } else if from.is_signed() {
+                    self.builder
+                        .build_int_s_extend(value, target, "casttmp")
+                        .into()
+                } else {
+                    self.builder
+                        .build_int_z_extend(value, target, "casttmp")
+                        .into()
+                }
+            }
+            (Type::Int(from), Type::Float(to)) => {
+                let value = value.into_int_value();
+                let target = self.float_type(*to);
+
+                if from.is_signed() {
+                    self.builder
+                        .build_signed_int_to_float(value, target, "casttmp")
+                        .into()
+                } else {
+                    self.builder
+                        .build_unsigned_int_to_float(value, target, "casttmp")
+                        .into()
+                }
+            }
+            (Type::Float(from), Type::Float(to)) => {
+                let value = value.into_float_value();
+                let target = self.float_type(*to);
+
+                match (from, to) {
+                    (ast::FloatType::F32, ast::FloatType::F64) => self
+                        .builder
+                        .build_float_ext(value, target, "casttmp")
+                        .into(),
+                    (ast::FloatType::F64, ast::FloatType::F32) => self
+                        .builder
+                        .build_float_trunc(value, target, "casttmp")
+                        .into(),
+                    _ => value.into(),
+                }
+            }
+            // saturates like Rust and `const_eval`, a plain `fptosi` or
+            // `fptoui` is poison when the value is out of range
+            (Type::Float(from), Type::Int(to)) => {
+                let sign = if to.is_signed() { "s" } else { "u" };
+                let from_bits = match from {
+                    ast::FloatType::F32 => 32,
+                    ast::FloatType::F64 => 64,
+                };
+                let name = format!("llvm.fpto{}i.sat.i{}.f{}", sign, to.bits(), from_bits);
+
+                let intrinsic = self.module.get_function(&name).unwrap_or_else(|| {
+                    let fn_type = self
+                        .int_type(*to)
+                        .fn_type(&[self.float_type(*from).into()], false);
+                    self.module.add_function(&name, fn_type, None)
+                });
+
                 self.builder
-                    .build_load(*self.variables.get(&name.name)?, name.name.as_str())
-                    .into_int_value(),
-            ),
-
-            Expr::Binary(op, lhs, rhs) => {
-                let lhs = self.compile_expr(lhs)?;
-                let rhs = self.compile_expr(rhs)?;
-
-                Some(match op {
-                    Operator::Add => self.builder.build_int_add(lhs, rhs, "addtmp"),
-                    Operator::Sub => self.builder.build_int_sub(lhs, rhs, "subtmp"),
-                    Operator::Mul => self.builder.build_int_mul(lhs, rhs, "multmp"),
-                    Operator::Div => self.builder.build_int_signed_div(lhs, rhs, "divtmp"),
+                    .build_call(intrinsic, &[value.into()], "casttmp")
+                    .try_as_basic_value()
+                    .left()
+                    .expect("the intrinsic returns an integer")
+            }
+            (Type::Bool, Type::Int(to)) => self
+                .builder
+                .build_int_z_extend(value.into_int_value(), self.int_type(*to), "casttmp")
+                .into(),
+            (Type::Pointer(_), Type::Pointer(_)) => self
+                .builder
+                .build_pointer_cast(
+                    value.into_pointer_value(),
+                    self.basic_type(to).into_pointer_type(),
+                    "casttmp",
+                )
+                .into(),
+            _ => value,
+        }
+    }
+
+    fn compile_expr(&mut self, expr: &Expr) -> CompileResult<BasicValueEnum<'ctx>> {
+        match &expr.kind {
+            ExprKind::IntLiteral(int) => {
+                let ty = match expr.ty {
+                    Type::Int(int) => int,
+                    _ => unreachable!("integer literals are typed as integers"),
+                };
+                Ok(self.int_type(ty).const_int(*int, false).into())
+            }
+            ExprKind::FloatLiteral(float) => {
+                let ty = match expr.ty {
+                    Type::Float(float) => float,
+                    _ => unreachable!("float literals are typed as floats"),
+                };
+                Ok(self.float_type(ty).const_float(*float).into())
+            }
+            ExprKind::BoolLiteral(boolean) => Ok(self
+                .context
+                .bool_type()
+                .const_int(*boolean as u64, false)
+                .into()),
+            ExprKind::CharLiteral(char) => Ok(self
+                .context
+                .i32_type()
+                .const_int(*char as u64, false)
+                .into()),
+            ExprKind::StringLiteral(string) => Ok(self.const_str(string).into()),
+            ExprKind::Variable(id) => Ok(self
+                .builder
+                .build_load(self.get_variable(*id), &self.program.binding(*id).name)),
+
+            ExprKind::Unary(op, operand) => {
+                let operand = self.compile_expr(operand)?;
+
+                Ok(match (op, operand) {
+                    (UnaryOperator::Neg, BasicValueEnum::FloatValue(operand)) => {
+                        self.builder.build_float_neg(operand, "negtmp").into()
+                    }
+                    (UnaryOperator::Neg, operand) => self
+                        .builder
+                        .build_int_neg(operand.into_int_value(), "negtmp")
+                        .into(),
+                    (UnaryOperator::Not, operand) => self
+                        .builder
+                        .build_not(operand.into_int_value(), "nottmp")
+                        .into(),
                 })
             }
 
-            Expr::Call(name, args) => {
-                let fun = self.get_function(&name.name)?;
+            ExprKind::Binary(op, lhs, rhs) => self.compile_binary(op, lhs, rhs),
+
+            ExprKind::Logical(op, lhs, rhs) => {
+                let parent = self.fn_value();
+
+                let lhs = self.compile_expr(lhs)?.into_int_value();
+                let lhs_bb = self.builder.get_insert_block().unwrap();
+
+                let rhs_bb = self.context.append_basic_block(parent, "logicrhs");
+                let merge_bb = self.context.append_basic_block(parent, "logiccont");
+
+                // `&&` skips the right side when the left one is false, `||`
+                // when it is true; either way the skipped value is the result
+                match op {
+                    LogicalOperator::And => {
+                        self.builder.build_conditional_branch(lhs, rhs_bb, merge_bb)
+                    }
+                    LogicalOperator::Or => {
+                        self.builder.build_conditional_branch(lhs, merge_bb, rhs_bb)
+                    }
+                };
+
+                self.builder.position_at_end(rhs_bb);
+                let rhs = self.compile_expr(rhs)?.into_int_value();
+                let rhs_bb = self.builder.get_insert_block().unwrap();
+                self.builder.build_unconditional_branch(merge_bb);
+
+                self.builder.position_at_end(merge_bb);
+                let short_circuit = self
+                    .context
+                    .bool_type()
+                    .const_int((*op == LogicalOperator::Or) as u64, false);
+                let phi = self.builder.build_phi(self.context.bool_type(), "logictmp");
+                phi.add_incoming(&[(&short_circuit, lhs_bb), (&rhs, rhs_bb)]);
+
+                Ok(phi.as_basic_value())
+            }
+
+            ExprKind::Cast(operand) => {
+                let value = self.compile_expr(operand)?;
+                Ok(self.compile_cast(value, &operand.ty, &expr.ty))
+            }
+
+            ExprKind::Call(name, args) => {
+                let fun = self
+                    .get_function(name)
+                    .expect("calls are resolved during lowering");
+
+                let is_extern = self.program.extern_function(name).is_some();
+                let is_runtime = runtime_function(name).is_some();
+                let mut compiled_args: Vec<BasicValueEnum> = Vec::with_capacity(args.len() + 1);
 
-                let mut compiled_args = Vec::with_capacity(args.len());
+                let ret_slot = if expr.ty.is_aggregate() || (is_runtime && expr.ty == Type::Str) {
+                    let slot = self.create_entry_block_alloca("ret", self.basic_type(&expr.ty));
+                    compiled_args.push(slot.into());
+                    Some(slot)
+                } else {
+                    None
+                };
 
                 for arg in args {
-                    compiled_args.push(self.compile_expr(arg)?);
+                    let value = self.compile_expr(arg)?;
+
+                    // the runtime gets the pointer and the length of a
+                    // string, extern functions only the pointer
+                    if is_runtime && arg.ty == Type::Str {
+                        let (ptr, len) = self.str_fields(value);
+                        compiled_args.push(ptr.into());
+                        compiled_args.push(len.into());
+                        continue;
+                    }
+
+                    let value = match (is_extern, &arg.ty) {
+                        (true, Type::Str) => self
+                            .builder
+                            .build_extract_value(value.into_struct_value(), 0, "strptr")
+                            .expect("str has a pointer field"),
+                        (_, ty) if ty.is_aggregate() => {
+                            let copy = self.create_entry_block_alloca("arg", self.basic_type(ty));
+                            self.builder.build_store(copy, value);
+                            copy.into()
+                        }
+                        _ => value,
+                    };
+                    compiled_args.push(value);
                 }
 
                 let argsv: Vec<BasicMetadataValueEnum> = compiled_args
@@ -81,152 +827,799 @@ impl<'a, 'ctx> Compiler<'a, 'ctx> {
                     .map(|&val| val.into())
                     .collect();
 
-                Some(
-                    self.builder
-                        .build_call(fun, &argsv, "tmp")
+                let call = self.builder.build_call(fun, &argsv, "tmp");
+
+                // functions returning a struct, an enum or `unit` are void in
+                // LLVM, apart from the exit status of `main`, and so are
+                // runtime functions returning a `str`
+                if let Some(slot) = ret_slot {
+                    Ok(self.builder.build_load(slot, "ret"))
+                } else if expr.ty == Type::Unit {
+                    Ok(self.unit_value())
+                } else {
+                    Ok(call
                         .try_as_basic_value()
-                        .left()?
-                        .into_int_value(),
-                )
+                        .left()
+                        .expect("only unit functions are void"))
+                }
+            }
+
+            ExprKind::StructLiteral(fields) => {
+                let mut value = self.basic_type(&expr.ty).into_struct_type().get_undef();
+
+                for (index, field) in fields {
+                    let field = self.compile_expr(field)?;
+                    value = self
+                        .builder
+                        .build_insert_value(value, field, *index as u32, "fieldtmp")
+                        .expect("field index is in bounds")
+                        .into_struct_value();
+                }
+
+                Ok(value.into())
+            }
+
+            ExprKind::Field(..) => {
+                let field = self.compile_place(expr)?;
+                Ok(self.builder.build_load(field, "field"))
+            }
+
+            ExprKind::Variant(index, values) => {
+                let name = enum_name(&expr.ty);
+                let slot = self.create_entry_block_alloca("variant", self.basic_type(&expr.ty));
+
+                let tag = self
+                    .builder
+                    .build_struct_gep(slot, 0, "tag")
+                    .expect("enums have a tag");
+                let tag_value = self.context.i32_type().const_int(*index as u64, false);
+                self.builder.build_store(tag, tag_value);
+
+                for (field, value) in values.iter().enumerate() {
+                    let value = self.compile_expr(value)?;
+                    let field = self.payload_field(slot, name, *index, field);
+                    self.builder.build_store(field, value);
+                }
+
+                Ok(self.builder.build_load(slot, "variant"))
+            }
+
+            ExprKind::Match(scrutinee, arms) => self.compile_match(scrutinee, arms, &expr.ty),
+
+            ExprKind::ArrayLiteral(values) => {
+                let mut array = self.basic_type(&expr.ty).into_array_type().get_undef();
+
+                for (index, value) in values.iter().enumerate() {
+                    let value = self.compile_expr(value)?;
+                    array = self
+                        .builder
+                        .build_insert_value(array, value, index as u32, "elementtmp")
+                        .expect("element index is in bounds")
+                        .into_array_value();
+                }
+
+                Ok(array.into())
+            }
+
+            ExprKind::Index(..) => {
+                let element = self.compile_place(expr)?;
+                Ok(self.builder.build_load(element, "element"))
+            }
+
+            ExprKind::Null => Ok(self
+                .basic_type(&expr.ty)
+                .into_pointer_type()
+                .const_null()
+                .into()),
+            ExprKind::AddressOf(place) => Ok(self.compile_place(place)?.into()),
+            ExprKind::Deref(pointer) => {
+                let pointer = self.compile_expr(pointer)?.into_pointer_value();
+                Ok(self.builder.build_load(pointer, "deref"))
+            }
+            ExprKind::Alloc(value) => {
+                let ty = self.basic_type(&value.ty);
+                let value = self.compile_expr(value)?;
+                let pointer = self
+                    .builder
+                    .build_malloc(ty, "alloc")
+                    .expect("every type of a value is sized");
+                self.builder.build_store(pointer, value);
+                Ok(pointer.into())
+            }
+            ExprKind::Free(pointer) => {
+                let pointer = self.compile_expr(pointer)?.into_pointer_value();
+                self.builder.build_free(pointer);
+                Ok(self.unit_value())
+            }
+
+            ExprKind::AsSlice(array) => {
+                let len = match &array.ty {
+                    Type::Array(_, len) => *len,
+                    _ => unreachable!("only arrays are turned into slices"),
+                };
+
+                let array = self.compile_place(array)?;
+                let elements = self.first_element(array);
+                let len = self.context.i64_type().const_int(len, false);
+
+                let slice = self.basic_type(&expr.ty).into_struct_type().get_undef();
+                let slice = self
+                    .builder
+                    .build_insert_value(slice, elements, 0, "slice")
+                    .expect("slices have a pointer field")
+                    .into_struct_value();
+                Ok(self
+                    .builder
+                    .build_insert_value(slice, len, 1, "slice")
+                    .expect("slices have a length field")
+                    .into_struct_value()
+                    .into())
+            }
+
+            ExprKind::Len(operand) => match &operand.ty {
+                Type::Array(_, len) => {
+                    // the length is known, the array is only evaluated for
+                    // the side effects of computing it
+                    if !operand.kind.is_place() {
+                        self.compile_expr(operand)?;
+                    }
+                    Ok(self.context.i64_type().const_int(*len, false).into())
+                }
+                _ => {
+                    let value = self.compile_expr(operand)?.into_struct_value();
+                    Ok(self
+                        .builder
+                        .build_extract_value(value, 1, "len")
+                        .expect("slices and strings have a length field"))
+                }
+            },
+        }
+    }
+
+    /// Compiles a `match` to a `switch` on the tag of an enum, or on the value
+    /// of an integer, a boolean or a character. Every case then tests the arms
+    /// that can match its variant or value in order, and jumps to the block
+    /// of the first one matching. The arms are known to be exhaustive.
+    fn compile_match(
+        &mut self,
+        scrutinee: &Expr,
+        arms: &[Arm],
+        ty: &Type,
+    ) -> CompileResult<BasicValueEnum<'ctx>> {
+        let parent = self.fn_value();
+
+        let value = self.compile_expr(scrutinee)?;
+        let slot = self.create_entry_block_alloca("scrutinee", self.basic_type(&scrutinee.ty));
+        self.builder.build_store(slot, value);
+
+        let result = match ty {
+            Type::Unit => None,
+            ty => Some(self.create_entry_block_alloca("matchresult", self.basic_type(ty))),
+        };
+
+        let mut heads: Vec<u64> = Vec::new();
+        for head in arms.iter().filter_map(|arm| pattern_head(&arm.pattern)) {
+            if !heads.contains(&head) {
+                heads.push(head);
+            }
+        }
+
+        let default_bb = self.context.append_basic_block(parent, "matchdefault");
+        let cases: Vec<_> = heads
+            .iter()
+            .map(|&head| (head, self.context.append_basic_block(parent, "matchcase")))
+            .collect();
+        let arm_bbs: Vec<_> = arms
+            .iter()
+            .map(|_| self.context.append_basic_block(parent, "matcharm"))
+            .collect();
+        let end_bb = self.context.append_basic_block(parent, "matchend");
+
+        if cases.is_empty() {
+            self.builder.build_unconditional_branch(default_bb);
+        } else {
+            let (discriminant, discriminant_type) = match &scrutinee.ty {
+                Type::Enum(_) => {
+                    let tag = self
+                        .builder
+                        .build_struct_gep(slot, 0, "tag")
+                        .expect("enums have a tag");
+                    let tag = self.builder.build_load(tag, "tag").into_int_value();
+                    (tag, self.context.i32_type())
+                }
+                ty => (value.into_int_value(), self.basic_type(ty).into_int_type()),
+            };
+
+            let switch_cases: Vec<_> = cases
+                .iter()
+                .map(|&(head, block)| (discriminant_type.const_int(head, false), block))
+                .collect();
+            self.builder
+                .build_switch(discriminant, default_bb, &switch_cases);
+        }
+
+        for &(head, block) in &cases {
+            self.builder.position_at_end(block);
+            self.compile_arm_tests(arms, Some(head), slot, &arm_bbs)?;
+        }
+
+        self.builder.position_at_end(default_bb);
+        self.compile_arm_tests(arms, None, slot, &arm_bbs)?;
+
+        for (arm, &block) in arms.iter().zip(&arm_bbs) {
+            self.builder.position_at_end(block);
+            self.bind_pattern(&arm.pattern, slot);
+            self.compile_block(&arm.body)?;
+
+            if let Some(value) = &arm.value {
+                let value = self.compile_expr(value)?;
+                if let Some(result) = result {
+                    self.builder.build_store(result, value);
+                }
             }
+
+            self.branch_to(end_bb);
         }
+
+        // when every arm returns, nothing reaches the end and the code
+        // following the `match` is dead
+        self.builder.position_at_end(end_bb);
+        Ok(match result {
+            Some(result) => self.builder.build_load(result, "matchtmp"),
+            None => self.unit_value(),
+        })
     }
 
-    fn compile_stmt(&mut self, stmt: &Stmt) -> Option<()> {
-        match stmt {
-            Stmt::Expr(expr) => {
+    /// Tests the arms whose pattern starts with the variant or value `head`,
+    /// or matches anything, and jumps to the block of the first that matches.
+    fn compile_arm_tests(
+        &mut self,
+        arms: &[Arm],
+        head: Option<u64>,
+        slot: PointerValue<'ctx>,
+        arm_bbs: &[BasicBlock<'ctx>],
+    ) -> CompileResult<()> {
+        let parent = self.fn_value();
+
+        for (arm, &block) in arms.iter().zip(arm_bbs) {
+            let arm_head = pattern_head(&arm.pattern);
+            if arm_head.is_some() && arm_head != head {
+                continue;
+            }
+
+            if arm.pattern.is_irrefutable() {
+                self.builder.build_unconditional_branch(block);
+                return Ok(());
+            }
+
+            let next_bb = self.context.append_basic_block(parent, "nextarm");
+            self.compile_pattern_test(&arm.pattern, slot, next_bb)?;
+            self.builder.build_unconditional_branch(block);
+            self.builder.position_at_end(next_bb);
+        }
+
+        // every value is matched by some arm
+        self.builder.build_unreachable();
+        Ok(())
+    }
+
+    /// Checks the value stored at `ptr` against a pattern. Control continues
+    /// in the current block if it matches and jumps to `fail` otherwise.
+    fn compile_pattern_test(
+        &mut self,
+        pattern: &Pattern,
+        ptr: PointerValue<'ctx>,
+        fail: BasicBlock<'ctx>,
+    ) -> CompileResult<()> {
+        match &pattern.kind {
+            PatternKind::Wildcard | PatternKind::Binding(_) => {}
+            PatternKind::Literal(literal) => {
+                let value = self.builder.build_load(ptr, "value").into_int_value();
+                let literal = self.compile_expr(literal)?.into_int_value();
+                self.branch_unless_equal(value, literal, fail);
+            }
+            PatternKind::Variant(index, fields) => {
+                let name = enum_name(&pattern.ty);
+
+                let tag = self
+                    .builder
+                    .build_struct_gep(ptr, 0, "tag")
+                    .expect("enums have a tag");
+                let tag = self.builder.build_load(tag, "tag").into_int_value();
+                let expected = self.context.i32_type().const_int(*index as u64, false);
+                self.branch_unless_equal(tag, expected, fail);
+
+                for (i, field) in fields.iter().enumerate() {
+                    let field_ptr = self.payload_field(ptr, name, *index, i);
+                    self.compile_pattern_test(field, field_ptr, fail)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn branch_unless_equal(
+        &self,
+        lhs: IntValue<'ctx>,
+        rhs: IntValue<'ctx>,
+        fail: BasicBlock<'ctx>,
+    ) {
+        let matched_bb = self
+            .context
+            .append_basic_block(self.fn_value(), "patmatched");
+        let equal = self
+            .builder
+            .build_int_compare(IntPredicate::EQ, lhs, rhs, "patcmp");
+        self.builder
+            .build_conditional_branch(equal, matched_bb, fail);
+        self.builder.position_at_end(matched_bb);
+    }
+
+    /// Stores the parts of the value at `ptr` in the variables the pattern
+    /// binds.
+    fn bind_pattern(&mut self, pattern: &Pattern, ptr: PointerValue<'ctx>) {
+        match &pattern.kind {
+            PatternKind::Wildcard | PatternKind::Literal(_) => {}
+            PatternKind::Binding(id) => {
+                let value = self.builder.build_load(ptr, "bound");
+                let alloca = self.declare_variable(*id);
+                self.builder.build_store(alloca, value);
+            }
+            PatternKind::Variant(index, fields) => {
+                let name = enum_name(&pattern.ty);
+
+                for (i, field) in fields.iter().enumerate() {
+                    let field_ptr = self.payload_field(ptr, name, *index, i);
+                    self.bind_pattern(field, field_ptr);
+                }
+            }
+        }
+    }
+
+    fn compile_stmt(&mut self, stmt: &Stmt) -> CompileResult<()> {
+        match &stmt.kind {
+            StmtKind::Expr(expr) => {
                 self.compile_expr(expr)?;
             }
-            Stmt::Let(name, expr) => todo!(),
-            Stmt::Return(expr) => {
-                let expr = self.compile_expr(expr)?;
-                self.builder.build_return(Some(&expr));
+            StmtKind::Let(id, expr) => {
+                let value = self.compile_expr(expr)?;
+                let alloca = self.declare_variable(*id);
+
+                self.builder.build_store(alloca, value);
+            }
+            StmtKind::Return(Some(expr)) => {
+                let value = self.compile_expr(expr)?;
+
+                // structs and enums are stored to the slot of the caller, `unit`
+                // functions are void and their value is never returned
+                if let Some(sret) = self.sret {
+                    self.builder.build_store(sret, value);
+                    self.builder.build_return(None);
+                } else if expr.ty == Type::Unit {
+                    self.build_unit_return();
+                } else {
+                    self.builder.build_return(Some(&value));
+                }
             }
-            Stmt::If(cond, then_b, else_b) => {
+            StmtKind::Return(None) => self.build_unit_return(),
+            StmtKind::If(cond, then_b, else_b) => {
                 let parent = self.fn_value();
-                let zero = self.context.i64_type().const_int(0, true);
 
-                let cond = self.compile_expr(cond)?;
-                let cond = self
-                    .builder
-                    .build_int_compare(IntPredicate::NE, cond, zero, "ifcond");
+                let cond = self.compile_expr(cond)?.into_int_value();
 
                 let then_bb = self.context.append_basic_block(parent, "then");
-                let else_bb = self.context.append_basic_block(parent, "else");
+                let else_bb = else_b
+                    .as_ref()
+                    .map(|_| self.context.append_basic_block(parent, "else"));
                 let cont_bb = self.context.append_basic_block(parent, "ifcont");
 
+                // without an `else` a false condition skips straight ahead
                 self.builder
-                    .build_conditional_branch(cond, then_bb, else_bb);
+                    .build_conditional_branch(cond, then_bb, else_bb.unwrap_or(cont_bb));
 
-                //build then
                 self.builder.position_at_end(then_bb);
-                for stmt in then_b {
-                    self.compile_stmt(stmt)?;
+                self.compile_block(then_b)?;
+                let then_terminated = self.is_terminated();
+                self.branch_to(cont_bb);
+
+                let else_terminated = match (else_bb, else_b) {
+                    (Some(else_bb), Some(else_b)) => {
+                        self.builder.position_at_end(else_bb);
+                        self.compile_block(else_b)?;
+                        let terminated = self.is_terminated();
+                        self.branch_to(cont_bb);
+                        terminated
+                    }
+                    _ => false,
+                };
+
+                self.builder.position_at_end(cont_bb);
+
+                // when both branches return, nothing reaches the merge block
+                // and the code following the `if` is dead
+                if then_terminated && else_terminated {
+                    self.builder.build_unreachable();
                 }
-                self.builder.build_unconditional_branch(cont_bb);
+            }
+            StmtKind::Loop(cond, body, step) => {
+                let parent = self.fn_value();
 
-                // let then_bb = self.builder.get_insert_block().unwrap();
+                let cond_bb = self.context.append_basic_block(parent, "loopcond");
+                let body_bb = self.context.append_basic_block(parent, "loop");
+                let latch_bb = self.context.append_basic_block(parent, "looplatch");
+                let after_bb = self.context.append_basic_block(parent, "afterloop");
+
+                self.builder.build_unconditional_branch(cond_bb);
+                self.builder.position_at_end(cond_bb);
 
-                //build else
-                self.builder.position_at_end(else_bb);
-                for stmt in else_b {
-                    self.compile_stmt(stmt)?;
+                match cond {
+                    Some(cond) => {
+                        let cond = self.compile_expr(cond)?.into_int_value();
+                        self.builder
+                            .build_conditional_branch(cond, body_bb, after_bb);
+                    }
+                    None => {
+                        self.builder.build_unconditional_branch(body_bb);
+                    }
                 }
-                self.builder.build_unconditional_branch(cont_bb);
 
-                // let else_bb = self.builder.get_insert_block().unwrap();
+                self.loops.push(LoopTarget {
+                    latch: latch_bb,
+                    exit: after_bb,
+                    broken: false,
+                });
 
-                // emit merge block
-                self.builder.position_at_end(cont_bb);
-                // TODO: make properly phi functions
+                self.builder.position_at_end(body_bb);
+                let body_result = self.compile_block(body);
+                let target = self.loops.pop().expect("loop target was pushed above");
+                body_result?;
+                self.branch_to(latch_bb);
+
+                self.builder.position_at_end(latch_bb);
+                self.compile_block(step)?;
+                self.branch_to(cond_bb);
+
+                self.builder.position_at_end(after_bb);
+
+                // a `loop` is only left through `break`
+                if cond.is_none() && !target.broken {
+                    self.builder.build_unreachable();
+                }
             }
-            Stmt::For(pre_stmt, cond, step, block) => {
-                let parent = self.fn_value();
+            StmtKind::Break => {
+                let target = self
+                    .loops
+                    .last_mut()
+                    .expect("`break` outside of a loop is rejected by the resolver");
+                target.broken = true;
+                let exit = target.exit;
+                self.builder.build_unconditional_branch(exit);
+            }
+            StmtKind::Continue => {
+                let target = self
+                    .loops
+                    .last()
+                    .expect("`continue` outside of a loop is rejected by the resolver");
+                self.builder.build_unconditional_branch(target.latch);
+            }
+            StmtKind::Block(block) => self.compile_block(block)?,
+            StmtKind::Assign(place, expr) => {
+                let value = self.compile_expr(expr)?;
+                let place = self.compile_place(place)?;
 
-                self.compile_stmt(pre_stmt)?;
+                self.builder.build_store(place, value);
+            }
+        }
+        Ok(())
+    }
 
-                let loop_bb = self.context.append_basic_block(parent, "loop");
+    /// Type of a value passed to an extern function. C knows nothing about
+    /// the length of a `str`, it only gets the pointer to the bytes, which
+    /// are NUL terminated.
+    fn c_type(&self, ty: &Type) -> BasicTypeEnum<'ctx> {
+        match ty {
+            Type::Str => self
+                .context
+                .i8_type()
+                .ptr_type(AddressSpace::default())
+                .into(),
+            ty => self.basic_type(ty),
+        }
+    }
 
-                self.builder.build_unconditional_branch(loop_bb);
-                self.builder.position_at_end(loop_bb);
+    /// Functions returning `unit` are void, so they can be called from C.
+    fn fn_type(
+        &self,
+        params: &[BasicTypeEnum<'ctx>],
+        ret: &Type,
+        variadic: bool,
+    ) -> FunctionType<'ctx> {
+        let args_types: Vec<BasicMetadataTypeEnum> = params.iter().map(|&ty| ty.into()).collect();
 
-                let after_bb = self.context.append_basic_block(parent, "afterloop");
+        match ret {
+            Type::Unit => self.context.void_type().fn_type(&args_types, variadic),
+            ret => self.basic_type(ret).fn_type(&args_types, variadic),
+        }
+    }
 
-                let cond = self.compile_expr(cond)?;
-                let zero = self.context.i64_type().const_int(0, true);
+    /// Declares the functions of the runtime library backing the built-ins.
+    /// A `str` is split into its pointer and length, or returned through a
+    /// pointer, see `RuntimeFunction`.
+    fn declare_runtime(&self) {
+        let str_ptr_type = self.str_type().ptr_type(AddressSpace::default());
+        let str_fields = self.str_type().get_field_types();
 
-                let cond = self
-                    .builder
-                    .build_int_compare(IntPredicate::NE, cond, zero, "loopcond");
+        for function in RUNTIME_FUNCTIONS {
+            let mut params: Vec<BasicTypeEnum> = Vec::new();
 
-                self.builder
-                    .build_conditional_branch(cond, loop_bb, after_bb);
+            let ret = match &function.ret {
+                Type::Str => {
+                    params.push(str_ptr_type.into());
+                    Type::Unit
+                }
+                ret => ret.clone(),
+            };
 
-                for stmt in block {
-                    self.compile_stmt(stmt)?;
+            for ty in function.params {
+                match ty {
+                    Type::Str => params.extend(&str_fields),
+                    ty => params.push(self.basic_type(ty)),
                 }
+            }
 
-                self.compile_expr(step)?;
+            let fn_type = self.fn_type(&params, &ret, false);
+            self.module
+                .add_function(function.name, fn_type, Some(Linkage::External));
+        }
+    }
 
-                self.builder.build_unconditional_branch(loop_bb);
+    fn declare_extern(&self, function: &ExternFunction) -> FunctionValue<'ctx> {
+        let params: Vec<_> = function.params.iter().map(|ty| self.c_type(ty)).collect();
 
-                self.builder.position_at_end(after_bb);
+        let fn_type = self.fn_type(&params, &function.ret, function.variadic);
+        self.module
+            .add_function(&function.name, fn_type, Some(Linkage::External))
+    }
+
+    /// Constant of the value a static is initialized with, which has been
+    /// folded into literals by `const_eval`.
+    fn const_value(&self, expr: &Expr) -> BasicValueEnum<'ctx> {
+        match &expr.kind {
+            ExprKind::IntLiteral(bits) => self
+                .basic_type(&expr.ty)
+                .into_int_type()
+                .const_int(*bits, false)
+                .into(),
+            ExprKind::FloatLiteral(float) => self
+                .basic_type(&expr.ty)
+                .into_float_type()
+                .const_float(*float)
+                .into(),
+            ExprKind::BoolLiteral(boolean) => self
+                .context
+                .bool_type()
+                .const_int(*boolean as u64, false)
+                .into(),
+            ExprKind::CharLiteral(char) => self
+                .context
+                .i32_type()
+                .const_int(*char as u64, false)
+                .into(),
+            ExprKind::StringLiteral(string) => self.const_str(string).into(),
+            ExprKind::Null => self
+                .basic_type(&expr.ty)
+                .into_pointer_type()
+                .const_null()
+                .into(),
+            ExprKind::ArrayLiteral(values) => {
+                let element = match &expr.ty {
+                    Type::Array(element, _) => self.basic_type(element),
+                    _ => unreachable!("array literals are typed as arrays"),
+                };
+                let values = values.iter().map(|value| self.const_value(value)).collect();
+                self.const_array(element, values).into()
             }
-            Stmt::Assign(_, _) => todo!(),
+            ExprKind::StructLiteral(fields) => {
+                let mut fields: Vec<_> = fields.iter().collect();
+                fields.sort_by_key(|(index, _)| *index);
+
+                let values: Vec<_> = fields
+                    .into_iter()
+                    .map(|(_, value)| self.const_value(value))
+                    .collect();
+                self.basic_type(&expr.ty)
+                    .into_struct_type()
+                    .const_named_struct(&values)
+                    .into()
+            }
+            _ => unreachable!("{:?} is not folded into a constant", expr.kind),
         }
-        Some(())
     }
 
-    pub fn make_prototype(&mut self, function: &Function) -> Option<FunctionValue<'ctx>> {
-        let ret_type = self.context.i64_type();
+    /// Constant array of values of the type `element`.
+    fn const_array(
+        &self,
+        element: BasicTypeEnum<'ctx>,
+        values: Vec<BasicValueEnum<'ctx>>,
+    ) -> ArrayValue<'ctx> {
+        let values = values.into_iter();
 
-        let args_types: Vec<BasicMetadataTypeEnum> = std::iter::repeat(ret_type)
-            .take(function.args.len())
-            .map(|x| x.into())
-            .collect();
+        match element {
+            BasicTypeEnum::IntType(ty) => {
+                ty.const_array(&values.map(|v| v.into_int_value()).collect::<Vec<_>>())
+            }
+            BasicTypeEnum::FloatType(ty) => {
+                ty.const_array(&values.map(|v| v.into_float_value()).collect::<Vec<_>>())
+            }
+            BasicTypeEnum::PointerType(ty) => {
+                ty.const_array(&values.map(|v| v.into_pointer_value()).collect::<Vec<_>>())
+            }
+            BasicTypeEnum::StructType(ty) => {
+                ty.const_array(&values.map(|v| v.into_struct_value()).collect::<Vec<_>>())
+            }
+            BasicTypeEnum::ArrayType(ty) => {
+                ty.const_array(&values.map(|v| v.into_array_value()).collect::<Vec<_>>())
+            }
+            BasicTypeEnum::VectorType(_) => unreachable!("no type is lowered to a vector"),
+        }
+    }
+
+    /// Defines the LLVM global of every static. They are only used in this
+    /// module, so their linkage is internal, and a static without `mut` is
+    /// constant.
+    fn define_statics(&mut self) {
+        for def in &self.program.statics {
+            let binding = self.program.binding(def.id);
+            let global = self
+                .module
+                .add_global(self.basic_type(&binding.ty), None, &binding.name);
 
-        let fn_type = self.context.i64_type().fn_type(&args_types, false);
-        let fn_val = self.module.add_function(&function.name.name, fn_type, None);
+            global.set_initializer(&self.const_value(&def.value));
+            global.set_linkage(Linkage::Internal);
+            global.set_constant(!def.mutable);
+
+            self.globals.insert(def.id, global.as_pointer_value());
+        }
+    }
+
+    /// Declares the named LLVM struct of every struct and enum in the
+    /// program.
+    fn declare_types(&self) {
+        // fields may refer to types declared later in the file
+        for def in &self.program.structs {
+            self.context
+                .opaque_struct_type(&format!("struct.{}", def.name));
+        }
 
-        for (i, arg) in fn_val.get_param_iter().enumerate() {
-            arg.into_int_value()
-                .set_name(function.args[i].name.as_str());
+        for def in &self.program.enums {
+            let enum_type = self
+                .context
+                .opaque_struct_type(&format!("enum.{}", def.name));
+            let words = self
+                .context
+                .i64_type()
+                .array_type(self.payload_words(&def.name) as u32);
+            enum_type.set_body(&[self.context.i32_type().into(), words.into()], false);
         }
 
-        Some(fn_val)
+        for def in &self.program.structs {
+            let fields: Vec<_> = def
+                .fields
+                .iter()
+                .map(|field| self.basic_type(&field.ty))
+                .collect();
+            self.struct_type(&def.name).set_body(&fields, false);
+        }
+    }
+
+    /// Returns from a function returning `unit`. That is a `void` function,
+    /// except for `main`, which returns the exit status 0 to the C runtime.
+    fn build_unit_return(&self) {
+        match self.fn_value().get_type().get_return_type() {
+            Some(status) => self
+                .builder
+                .build_return(Some(&status.into_int_type().const_zero())),
+            None => self.builder.build_return(None),
+        };
+    }
+
+    /// Declares a function compiled from the program. Structs and enums are
+    /// passed by reference: the caller copies the argument to its own stack
+    /// and passes a pointer to the copy, which the callee may modify. They
+    /// are returned through a pointer to a slot of the caller, which is
+    /// passed in front of the other arguments, and the function itself is
+    /// `void`.
+    pub fn make_prototype(&mut self, function: &Function) -> FunctionValue<'ctx> {
+        let sret = function.ret.is_aggregate();
+
+        let mut params = Vec::with_capacity(function.params.len() + 1);
+        if sret {
+            params.push(self.param_type(&function.ret));
+        }
+        params.extend(
+            function
+                .params
+                .iter()
+                .map(|id| self.param_type(&self.program.binding(*id).ty)),
+        );
+
+        let ret = match function.ret {
+            Type::Unit if function.name == "main" => Type::Int(ast::IntType::I32),
+            _ if sret => Type::Unit,
+            ref ret => ret.clone(),
+        };
+
+        let fn_type = self.fn_type(&params, &ret, false);
+        let fn_val = self.module.add_function(&function.name, fn_type, None);
+
+        let mut args = fn_val.get_param_iter();
+        if sret {
+            args.next().expect("sret parameter").set_name("sret");
+        }
+        for (arg, id) in args.zip(&function.params) {
+            arg.set_name(&self.program.binding(*id).name);
+        }
+
+        fn_val
     }
 
-    pub fn compile_function(&mut self, function: &Function) -> Option<FunctionValue<'ctx>> {
-        let func = self.make_prototype(function)?;
+    /// Compiles the body of a function whose prototype was already declared
+    /// by `make_prototype`.
+    pub fn compile_function(&mut self, function: &Function) -> CompileResult<FunctionValue<'ctx>> {
+        let func = self
+            .get_function(&function.name)
+            .expect("prototype is declared before the body is compiled");
 
         let entry = self.context.append_basic_block(func, "entry");
         self.builder.position_at_end(entry);
 
         self.fn_value_opt = Some(func);
-        self.variables.reserve(function.args.len());
+        self.variables.clear();
 
-        for (i, arg) in func.get_param_iter().enumerate() {
-            let arg_name = function.args[i].name.as_str();
-            let alloca = self.create_entry_block_alloca(arg_name);
+        let mut args = func.get_param_iter();
+        self.sret = if function.ret.is_aggregate() {
+            args.next().map(|arg| arg.into_pointer_value())
+        } else {
+            None
+        };
 
-            self.builder.build_store(alloca, arg);
+        for (arg, id) in args.zip(&function.params) {
+            // a struct or enum argument already is a copy owned by this
+            // function
+            if self.program.binding(*id).ty.is_aggregate() {
+                self.variables.insert(*id, arg.into_pointer_value());
+                continue;
+            }
 
-            self.variables.insert(function.args[i].name.clone(), alloca);
+            let alloca = self.declare_variable(*id);
+
+            self.builder.build_store(alloca, arg);
         }
 
-        for stmt in &function.body {
-            self.compile_stmt(stmt)?;
+        self.compile_block(&function.body)?;
+
+        // lowering rejects other functions that fall off the end of their
+        // body, so for those the current block is dead, like the one after a
+        // `match` whose arms all return
+        if !self.is_terminated() {
+            if function.ret == Type::Unit {
+                self.build_unit_return();
+            } else {
+                self.builder.build_unreachable();
+            }
         }
 
         if func.verify(true) {
             self.fpm.run_on(&func);
-            Some(func)
+            Ok(func)
         } else {
-            println!("not verified");
             unsafe {
                 func.delete();
             }
-            None
+            Err(CompileError::VerificationFailed {
+                name: function.name.clone(),
+                span: function.span,
+            })
         }
     }
 
@@ -235,17 +1628,103 @@ impl<'a, 'ctx> Compiler<'a, 'ctx> {
         builder: &'a Builder<'ctx>,
         pass_manager: &'a PassManager<FunctionValue<'ctx>>,
         module: &'a Module<'ctx>,
-        program: &Program,
-    ) -> Option<FunctionValue<'ctx>> {
+        program: &'a Program,
+        source: &'a SourceFile,
+        bounds_checks: bool,
+    ) -> CompileResult<Vec<FunctionValue<'ctx>>> {
         let mut compiler = Self {
             context,
             builder,
             fpm: pass_manager,
             module,
+            program,
+            source,
+            bounds_checks,
             fn_value_opt: None,
             variables: HashMap::new(),
+            globals: HashMap::new(),
+            loops: Vec::new(),
+            sret: None,
+            source_name: None,
         };
 
-        compiler.compile_function(&program.functions[0])
+        compiler.declare_types();
+        compiler.declare_runtime();
+
+        for function in &program.externs {
+            compiler.declare_extern(function);
+        }
+
+        // declare every prototype first, so that bodies can call functions
+        // defined later in the file and mutually recursive functions work
+        for function in &program.functions {
+            compiler.make_prototype(function);
+        }
+
+        // after the functions, so a static sharing the name of a function is
+        // the one LLVM renames
+        compiler.define_statics();
+
+        program
+            .functions
+            .iter()
+            .map(|function| compiler.compile_function(function))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use inkwell::{context::Context, passes::PassManager};
+
+    use super::*;
+    use crate::{lowering::lower_program, parser::parse_source, resolver::resolve_program};
+
+    /// Unoptimized LLVM IR of a program free of errors.
+    fn compile_ir(code: &str, bounds_checks: bool) -> String {
+        let source = SourceFile::new("test.ac".to_string(), code.to_string());
+        let (program, errors) = parse_source(code);
+        assert!(errors.is_empty(), "{:?}", errors);
+        let resolution = resolve_program(&program);
+        assert!(!resolution.has_errors(), "{:?}", resolution.diagnostics);
+        let program = lower_program(&program, &resolution.symbols).expect("program type checks");
+
+        let context = Context::create();
+        let module = context.create_module("test");
+        let builder = context.create_builder();
+        let fpm = PassManager::create(&module);
+        fpm.initialize();
+
+        Compiler::compile(
+            &context,
+            &builder,
+            &fpm,
+            &module,
+            &program,
+            &source,
+            bounds_checks,
+        )
+        .expect("program compiles");
+        module.print_to_string().to_string()
+    }
+
+    const INDEXING: &str = "fun get(values: [i64], i: i64) -> i64 {
+        return values[i];
+    }";
+
+    #[test]
+    fn checks_bounds_of_indexing() {
+        let ir = compile_ir(INDEXING, true);
+        assert!(ir.contains("call void @__ac_index_out_of_bounds"), "{}", ir);
+    }
+
+    #[test]
+    fn bounds_checks_can_be_turned_off() {
+        let ir = compile_ir(INDEXING, false);
+        assert!(
+            !ir.contains("call void @__ac_index_out_of_bounds"),
+            "{}",
+            ir
+        );
     }
 }
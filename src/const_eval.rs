@@ -0,0 +1,327 @@
+//! Evaluates the initializers of constants and statics while compiling. The
+//! initializer is lowered and type checked like any other expression first,
+//! then folded here into a literal, or an array or struct of literals, that
+//! can be inlined or become the initial value of an LLVM global.
+
+use crate::{
+    diagnostics::Span,
+    error::CompileError,
+    internal_representations::ast::{
+        Expr, ExprKind, FloatType, IntType, LogicalOperator, Operator, Type, UnaryOperator,
+    },
+};
+
+type EvalResult<T> = Result<T, CompileError>;
+
+/// Value of an integer of the type `int` from its bits, which may have been
+/// sign extended beyond the width of the type.
+fn int_value(bits: u64, int: IntType) -> i128 {
+    let shift = 64 - int.bits();
+
+    if int.is_signed() {
+        (((bits << shift) as i64) >> shift) as i128
+    } else {
+        ((bits << shift) >> shift) as i128
+    }
+}
+
+/// Bits of an integer literal of the type `int`, or an error when the value
+/// does not fit the type.
+fn int_bits(value: i128, int: IntType, span: Span) -> EvalResult<u64> {
+    let max = int.max() as i128;
+    let min = if int.is_signed() { -max - 1 } else { 0 };
+
+    if (min..=max).contains(&value) {
+        Ok(value as u64)
+    } else {
+        Err(CompileError::ConstantOverflow { span })
+    }
+}
+
+/// Rounds the result of a float operation to the precision of the type.
+fn round(value: f64, float: FloatType) -> f64 {
+    match float {
+        FloatType::F32 => value as f32 as f64,
+        FloatType::F64 => value,
+    }
+}
+
+fn unary(op: &UnaryOperator, operand: Expr, span: Span) -> EvalResult<ExprKind> {
+    Ok(match (op, operand.kind, &operand.ty) {
+        (UnaryOperator::Neg, ExprKind::IntLiteral(bits), Type::Int(int)) => {
+            ExprKind::IntLiteral(int_bits(-int_value(bits, *int), *int, span)?)
+        }
+        (UnaryOperator::Neg, ExprKind::FloatLiteral(float), _) => ExprKind::FloatLiteral(-float),
+        (UnaryOperator::Not, ExprKind::BoolLiteral(boolean), _) => ExprKind::BoolLiteral(!boolean),
+        (_, kind, _) => unreachable!("`{}` applied to {:?}", op.symbol(), kind),
+    })
+}
+
+/// Result of a comparison of two values of a type with a total order.
+fn compare<T: PartialOrd>(op: &Operator, lhs: T, rhs: T) -> ExprKind {
+    ExprKind::BoolLiteral(match op {
+        Operator::Eq => lhs == rhs,
+        Operator::Ne => lhs != rhs,
+        Operator::Lt => lhs < rhs,
+        Operator::Le => lhs <= rhs,
+        Operator::Gt => lhs > rhs,
+        Operator::Ge => lhs >= rhs,
+        _ => unreachable!("`{}` is not a comparison", op.symbol()),
+    })
+}
+
+fn binary(op: &Operator, lhs: Expr, rhs: Expr, span: Span) -> EvalResult<ExprKind> {
+    Ok(match (lhs.kind, rhs.kind, &lhs.ty) {
+        (ExprKind::IntLiteral(lhs), ExprKind::IntLiteral(rhs), Type::Int(int)) => {
+            let (lhs, rhs) = (int_value(lhs, *int), int_value(rhs, *int));
+
+            if *op == Operator::Div && rhs == 0 {
+                return Err(CompileError::DivisionByZero { span });
+            }
+
+            // the product of two large `u64` does not even fit an `i128`
+            let value = match op {
+                Operator::Add => lhs.checked_add(rhs),
+                Operator::Sub => lhs.checked_sub(rhs),
+                Operator::Mul => lhs.checked_mul(rhs),
+                Operator::Div => lhs.checked_div(rhs),
+                _ => return Ok(compare(op, lhs, rhs)),
+            };
+
+            match value {
+                Some(value) => ExprKind::IntLiteral(int_bits(value, *int, span)?),
+                None => return Err(CompileError::ConstantOverflow { span }),
+            }
+        }
+        (ExprKind::FloatLiteral(lhs), ExprKind::FloatLiteral(rhs), Type::Float(float)) => {
+            let value = match op {
+                Operator::Add => lhs + rhs,
+                Operator::Sub => lhs - rhs,
+                Operator::Mul => lhs * rhs,
+                Operator::Div => lhs / rhs,
+                _ => return Ok(compare(op, lhs, rhs)),
+            };
+
+            ExprKind::FloatLiteral(round(value, *float))
+        }
+        (ExprKind::BoolLiteral(lhs), ExprKind::BoolLiteral(rhs), _) => compare(op, lhs, rhs),
+        (ExprKind::CharLiteral(lhs), ExprKind::CharLiteral(rhs), _) => compare(op, lhs, rhs),
+        // the only constant pointer is `null`
+        (ExprKind::Null, ExprKind::Null, _) => compare(op, 0, 0),
+        (lhs, rhs, _) => unreachable!("`{}` applied to {:?} and {:?}", op.symbol(), lhs, rhs),
+    })
+}
+
+/// Converts a value like an `as` cast does at runtime. Floats saturate when
+/// they are converted to an integer too small to hold them, and NaN becomes
+/// 0, like the intrinsics `compile_cast` calls.
+fn cast(operand: Expr, ty: &Type) -> ExprKind {
+    if operand.ty == *ty {
+        return operand.kind;
+    }
+
+    // a `char` converts like the `u32` of its code point
+    let (kind, from) = match operand.kind {
+        ExprKind::CharLiteral(char) => (ExprKind::IntLiteral(char as u64), Type::Int(IntType::U32)),
+        kind => (kind, operand.ty),
+    };
+
+    match (kind, &from, ty) {
+        (ExprKind::IntLiteral(bits), Type::Int(from), Type::Int(to)) => {
+            let wrapped = int_value(int_value(bits, *from) as u64, *to);
+            ExprKind::IntLiteral(wrapped as u64)
+        }
+        (ExprKind::IntLiteral(bits), Type::Int(from), Type::Float(to)) => {
+            ExprKind::FloatLiteral(round(int_value(bits, *from) as f64, *to))
+        }
+        (ExprKind::IntLiteral(bits), Type::Int(_), Type::Char) => {
+            ExprKind::CharLiteral(char::from(bits as u8))
+        }
+        (ExprKind::FloatLiteral(float), _, Type::Int(to)) => {
+            let max = to.max() as i128;
+            let min = if to.is_signed() { -max - 1 } else { 0 };
+            ExprKind::IntLiteral((float as i128).clamp(min, max) as u64)
+        }
+        (ExprKind::FloatLiteral(float), _, Type::Float(to)) => {
+            ExprKind::FloatLiteral(round(float, *to))
+        }
+        (ExprKind::BoolLiteral(boolean), _, Type::Int(_)) => ExprKind::IntLiteral(boolean as u64),
+        // casts between pointer types change nothing
+        (kind, _, _) => kind,
+    }
+}
+
+/// Folds a lowered expression into a constant, or reports the first part of
+/// it that cannot be computed at compile time, like a call or a variable.
+pub fn evaluate(expr: Expr) -> EvalResult<Expr> {
+    let Expr { kind, ty, span } = expr;
+
+    let kind = match kind {
+        kind @ (ExprKind::IntLiteral(_)
+        | ExprKind::FloatLiteral(_)
+        | ExprKind::BoolLiteral(_)
+        | ExprKind::CharLiteral(_)
+        | ExprKind::StringLiteral(_)
+        | ExprKind::Null) => kind,
+        ExprKind::Unary(op, operand) => unary(&op, evaluate(*operand)?, span)?,
+        ExprKind::Binary(op, lhs, rhs) => binary(&op, evaluate(*lhs)?, evaluate(*rhs)?, span)?,
+        // both sides are evaluated, so a non-constant right side is
+        // reported even when it would not run
+        ExprKind::Logical(op, lhs, rhs) => {
+            let lhs = evaluate(*lhs)?.kind;
+            let rhs = evaluate(*rhs)?.kind;
+
+            match (lhs, rhs) {
+                (ExprKind::BoolLiteral(lhs), ExprKind::BoolLiteral(rhs)) => {
+                    ExprKind::BoolLiteral(match op {
+                        LogicalOperator::And => lhs && rhs,
+                        LogicalOperator::Or => lhs || rhs,
+                    })
+                }
+                (lhs, rhs) => unreachable!("`{}` applied to {:?} and {:?}", op.symbol(), lhs, rhs),
+            }
+        }
+        ExprKind::Cast(operand) => cast(evaluate(*operand)?, &ty),
+        ExprKind::Len(operand) => {
+            let operand = evaluate(*operand)?;
+
+            match (&operand.ty, &operand.kind) {
+                (Type::Array(_, len), _) => ExprKind::IntLiteral(*len),
+                (_, ExprKind::StringLiteral(string)) => ExprKind::IntLiteral(string.len() as u64),
+                _ => return Err(CompileError::NonConstant { span }),
+            }
+        }
+        ExprKind::ArrayLiteral(values) => ExprKind::ArrayLiteral(
+            values
+                .into_iter()
+                .map(evaluate)
+                .collect::<EvalResult<_>>()?,
+        ),
+        ExprKind::StructLiteral(fields) => ExprKind::StructLiteral(
+            fields
+                .into_iter()
+                .map(|(index, value)| Ok((index, evaluate(value)?)))
+                .collect::<EvalResult<_>>()?,
+        ),
+        _ => return Err(CompileError::NonConstant { span }),
+    };
+
+    Ok(Expr::new(kind, ty, span))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::internal_representations::ast::BindingId;
+
+    const U8: Type = Type::Int(IntType::U8);
+    const I8: Type = Type::Int(IntType::I8);
+    const U64: Type = Type::Int(IntType::U64);
+
+    fn expr(kind: ExprKind, ty: Type) -> Expr {
+        Expr::new(kind, ty, Span::default())
+    }
+
+    fn int(value: i128, ty: Type) -> Expr {
+        expr(ExprKind::IntLiteral(value as u64), ty)
+    }
+
+    fn float(value: f64) -> Expr {
+        expr(ExprKind::FloatLiteral(value), Type::F64)
+    }
+
+    fn binary(op: Operator, lhs: Expr, rhs: Expr) -> Expr {
+        let ty = lhs.ty.clone();
+        expr(ExprKind::Binary(op, Box::new(lhs), Box::new(rhs)), ty)
+    }
+
+    fn cast(operand: Expr, ty: Type) -> Expr {
+        expr(ExprKind::Cast(Box::new(operand)), ty)
+    }
+
+    fn value(expr: Expr) -> ExprKind {
+        evaluate(expr).expect("expression is constant").kind
+    }
+
+    fn overflows(expr: Expr) -> bool {
+        matches!(evaluate(expr), Err(CompileError::ConstantOverflow { .. }))
+    }
+
+    fn divides_by_zero(expr: Expr) -> bool {
+        matches!(evaluate(expr), Err(CompileError::DivisionByZero { .. }))
+    }
+
+    #[test]
+    fn folds_arithmetic() {
+        let sum = binary(Operator::Add, int(2, Type::I64), int(3, Type::I64));
+        let product = binary(Operator::Mul, sum, int(-4, Type::I64));
+        assert_eq!(value(product), ExprKind::IntLiteral(-20i64 as u64));
+
+        let less = binary(Operator::Lt, int(255, U8), int(1, U8));
+        assert_eq!(value(less), ExprKind::BoolLiteral(false));
+    }
+
+    #[test]
+    fn reports_overflow() {
+        let max = u64::MAX as i128;
+        assert!(overflows(binary(
+            Operator::Mul,
+            int(max, U64),
+            int(max, U64)
+        )));
+        assert!(overflows(binary(Operator::Add, int(max, U64), int(1, U64))));
+        assert!(overflows(binary(Operator::Sub, int(0, U8), int(1, U8))));
+        assert!(overflows(binary(Operator::Add, int(127, I8), int(1, I8))));
+        assert!(overflows(binary(Operator::Div, int(-128, I8), int(-1, I8))));
+
+        let neg = expr(
+            ExprKind::Unary(UnaryOperator::Neg, Box::new(int(-128, I8))),
+            I8,
+        );
+        assert!(overflows(neg));
+    }
+
+    #[test]
+    fn reports_division_by_zero() {
+        assert!(divides_by_zero(binary(
+            Operator::Div,
+            int(1, Type::I64),
+            int(0, Type::I64)
+        )));
+        assert!(divides_by_zero(binary(
+            Operator::Div,
+            int(0, U8),
+            int(0, U8)
+        )));
+    }
+
+    #[test]
+    fn casts_like_at_runtime() {
+        assert_eq!(
+            value(cast(int(300, Type::I64), U8)),
+            ExprKind::IntLiteral(44)
+        );
+        assert_eq!(
+            value(cast(int(-1, Type::I64), U64)),
+            ExprKind::IntLiteral(u64::MAX)
+        );
+        assert_eq!(
+            value(cast(int(255, U8), I8)),
+            ExprKind::IntLiteral(-1i64 as u64)
+        );
+        assert_eq!(value(cast(float(300.0), U8)), ExprKind::IntLiteral(255));
+        assert_eq!(value(cast(float(-3.7), U8)), ExprKind::IntLiteral(0));
+        assert_eq!(value(cast(float(f64::NAN), I8)), ExprKind::IntLiteral(0));
+    }
+
+    #[test]
+    fn rejects_variables() {
+        let variable = expr(ExprKind::Variable(BindingId(0)), Type::I64);
+        let sum = binary(Operator::Add, int(1, Type::I64), variable);
+        assert!(matches!(
+            evaluate(sum),
+            Err(CompileError::NonConstant { .. })
+        ));
+    }
+}
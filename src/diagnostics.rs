@@ -0,0 +1,139 @@
+use std::fmt::Write;
+
+use pest::error::InputLocation;
+
+/// Byte range of a node in the source file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    /// Smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+}
+
+impl From<pest::Span<'_>> for Span {
+    fn from(span: pest::Span<'_>) -> Self {
+        Span::new(span.start(), span.end())
+    }
+}
+
+impl From<InputLocation> for Span {
+    fn from(location: InputLocation) -> Self {
+        match location {
+            InputLocation::Pos(pos) => Span::new(pos, pos),
+            InputLocation::Span((start, end)) => Span::new(start, end),
+        }
+    }
+}
+
+pub struct SourceFile {
+    pub name: String,
+    pub code: String,
+}
+
+impl SourceFile {
+    pub fn new(name: String, code: String) -> Self {
+        SourceFile { name, code }
+    }
+
+    /// 1-based line and column of a byte offset.
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.code.len());
+        let before = &self.code[..offset];
+
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |pos| pos + 1);
+        let col = before[line_start..].chars().count() + 1;
+
+        (line, col)
+    }
+
+    fn line(&self, line: usize) -> &str {
+        self.code.lines().nth(line - 1).unwrap_or("")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub level: Level,
+    pub message: String,
+    pub span: Span,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>, span: Span) -> Self {
+        Diagnostic {
+            level: Level::Error,
+            message: message.into(),
+            span,
+        }
+    }
+
+    pub fn warning(message: impl Into<String>, span: Span) -> Self {
+        Diagnostic {
+            level: Level::Warning,
+            message: message.into(),
+            span,
+        }
+    }
+
+    /// Renders the diagnostic with the offending source line and the span
+    /// underlined by carets:
+    ///
+    /// ```text
+    /// error: unknown variable `x`
+    ///  --> main.ac:3:12
+    ///   |
+    /// 3 |     return x;
+    ///   |            ^
+    /// ```
+    pub fn render(&self, source: &SourceFile) -> String {
+        let level = match self.level {
+            Level::Error => "error",
+            Level::Warning => "warning",
+        };
+
+        let (line, col) = source.line_col(self.span.start);
+        let text = source.line(line);
+
+        // spans running over several lines are underlined up to the line end
+        let (end_line, end_col) = source.line_col(self.span.end);
+        let end_col = if end_line == line {
+            end_col
+        } else {
+            text.chars().count() + 1
+        };
+        let carets = end_col.saturating_sub(col).max(1);
+
+        let gutter = " ".repeat(line.to_string().len());
+        let padding: String = text
+            .chars()
+            .take(col - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        let mut result = String::new();
+        writeln!(result, "{}: {}", level, self.message).unwrap();
+        writeln!(result, "{}--> {}:{}:{}", gutter, source.name, line, col).unwrap();
+        writeln!(result, "{} |", gutter).unwrap();
+        writeln!(result, "{} | {}", line, text).unwrap();
+        writeln!(result, "{} | {}{}", gutter, padding, "^".repeat(carets)).unwrap();
+
+        result
+    }
+}
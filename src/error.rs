@@ -0,0 +1,354 @@
+use std::fmt;
+
+use crate::{
+    diagnostics::{Diagnostic, SourceFile, Span},
+    internal_representations::ast::Type,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompileError {
+    ParseError {
+        message: String,
+        span: Span,
+    },
+    UnknownVariable {
+        name: String,
+        span: Span,
+    },
+    UnknownFunction {
+        name: String,
+        span: Span,
+    },
+    ArityMismatch {
+        name: String,
+        expected: usize,
+        found: usize,
+        span: Span,
+    },
+    DuplicateFunction {
+        name: String,
+        span: Span,
+    },
+    DuplicateParameter {
+        name: String,
+        span: Span,
+    },
+    DuplicateType {
+        name: String,
+        span: Span,
+    },
+    DuplicateField {
+        name: String,
+        span: Span,
+    },
+    UnknownField {
+        ty: Type,
+        name: String,
+        span: Span,
+    },
+    MissingField {
+        ty: Type,
+        name: String,
+        span: Span,
+    },
+    UnknownVariant {
+        ty: Type,
+        name: String,
+        span: Span,
+    },
+    DuplicateVariant {
+        name: String,
+        span: Span,
+    },
+    RecursiveType {
+        name: String,
+        span: Span,
+    },
+    InvalidAssignment {
+        span: Span,
+    },
+    NotIndexable {
+        ty: Type,
+        span: Span,
+    },
+    EmptyArrayLiteral {
+        span: Span,
+    },
+    NotAddressable {
+        span: Span,
+    },
+    UntypedNull {
+        span: Span,
+    },
+    DuplicateGlobal {
+        name: String,
+        span: Span,
+    },
+    ImmutableAssignment {
+        name: String,
+        span: Span,
+    },
+    NonConstant {
+        span: Span,
+    },
+    ConstantOverflow {
+        span: Span,
+    },
+    DivisionByZero {
+        span: Span,
+    },
+    RecursiveConstant {
+        name: String,
+        span: Span,
+    },
+    DuplicateBinding {
+        name: String,
+        span: Span,
+    },
+    NonExhaustiveMatch {
+        pattern: String,
+        span: Span,
+    },
+    UnreachablePattern {
+        span: Span,
+    },
+    UnknownType {
+        name: String,
+        span: Span,
+    },
+    TypeMismatch {
+        expected: Type,
+        found: Type,
+        span: Span,
+    },
+    InvalidOperand {
+        operator: String,
+        ty: Type,
+        span: Span,
+    },
+    InvalidCast {
+        from: Type,
+        to: Type,
+        span: Span,
+    },
+    LiteralOutOfRange {
+        ty: Type,
+        span: Span,
+    },
+    LoopControlOutsideLoop {
+        keyword: String,
+        span: Span,
+    },
+    MissingReturn {
+        name: String,
+        ty: Type,
+        span: Span,
+    },
+    InvalidExternReturn {
+        ty: Type,
+        span: Span,
+    },
+    InvalidExternParameter {
+        ty: Type,
+        span: Span,
+    },
+    VerificationFailed {
+        name: String,
+        span: Span,
+    },
+}
+
+impl CompileError {
+    pub fn span(&self) -> Span {
+        match self {
+            CompileError::ParseError { span, .. }
+            | CompileError::UnknownVariable { span, .. }
+            | CompileError::UnknownFunction { span, .. }
+            | CompileError::ArityMismatch { span, .. }
+            | CompileError::DuplicateFunction { span, .. }
+            | CompileError::DuplicateParameter { span, .. }
+            | CompileError::DuplicateType { span, .. }
+            | CompileError::DuplicateField { span, .. }
+            | CompileError::UnknownField { span, .. }
+            | CompileError::MissingField { span, .. }
+            | CompileError::UnknownVariant { span, .. }
+            | CompileError::DuplicateVariant { span, .. }
+            | CompileError::RecursiveType { span, .. }
+            | CompileError::InvalidAssignment { span }
+            | CompileError::NotIndexable { span, .. }
+            | CompileError::EmptyArrayLiteral { span }
+            | CompileError::NotAddressable { span }
+            | CompileError::UntypedNull { span }
+            | CompileError::DuplicateGlobal { span, .. }
+            | CompileError::ImmutableAssignment { span, .. }
+            | CompileError::NonConstant { span }
+            | CompileError::ConstantOverflow { span }
+            | CompileError::DivisionByZero { span }
+            | CompileError::RecursiveConstant { span, .. }
+            | CompileError::DuplicateBinding { span, .. }
+            | CompileError::NonExhaustiveMatch { span, .. }
+            | CompileError::UnreachablePattern { span }
+            | CompileError::UnknownType { span, .. }
+            | CompileError::TypeMismatch { span, .. }
+            | CompileError::InvalidOperand { span, .. }
+            | CompileError::InvalidCast { span, .. }
+            | CompileError::LiteralOutOfRange { span, .. }
+            | CompileError::LoopControlOutsideLoop { span, .. }
+            | CompileError::MissingReturn { span, .. }
+            | CompileError::InvalidExternReturn { span, .. }
+            | CompileError::InvalidExternParameter { span, .. }
+            | CompileError::VerificationFailed { span, .. } => *span,
+        }
+    }
+
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        Diagnostic::error(self.to_string(), self.span())
+    }
+
+    pub fn render(&self, source: &SourceFile) -> String {
+        self.to_diagnostic().render(source)
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompileError::ParseError { message, .. } => write!(f, "{}", message),
+            CompileError::UnknownVariable { name, .. } => {
+                write!(f, "unknown variable `{}`", name)
+            }
+            CompileError::UnknownFunction { name, .. } => {
+                write!(f, "unknown function `{}`", name)
+            }
+            CompileError::ArityMismatch {
+                name,
+                expected,
+                found,
+                ..
+            } => write!(
+                f,
+                "function `{}` takes {} arguments but {} were supplied",
+                name, expected, found
+            ),
+            CompileError::DuplicateFunction { name, .. } => {
+                write!(f, "function `{}` is defined more than once", name)
+            }
+            CompileError::DuplicateParameter { name, .. } => {
+                write!(f, "parameter `{}` is declared more than once", name)
+            }
+            CompileError::DuplicateType { name, .. } => {
+                write!(f, "type `{}` is defined more than once", name)
+            }
+            CompileError::DuplicateField { name, .. } => {
+                write!(f, "field `{}` is given more than once", name)
+            }
+            CompileError::UnknownField { ty, name, .. } => {
+                write!(f, "type `{}` has no field `{}`", ty, name)
+            }
+            CompileError::MissingField { ty, name, .. } => {
+                write!(f, "missing field `{}` in literal of `{}`", name, ty)
+            }
+            CompileError::UnknownVariant { ty, name, .. } => {
+                write!(f, "type `{}` has no variant `{}`", ty, name)
+            }
+            CompileError::DuplicateVariant { name, .. } => {
+                write!(f, "variant `{}` is declared more than once", name)
+            }
+            CompileError::RecursiveType { name, .. } => write!(
+                f,
+                "type `{}` contains itself and would have an infinite size",
+                name
+            ),
+            CompileError::InvalidAssignment { .. } => write!(
+                f,
+                "only variables, their fields, elements and dereferenced pointers can be \
+                 assigned to"
+            ),
+            CompileError::NotIndexable { ty, .. } => {
+                write!(f, "cannot index into a value of type `{}`", ty)
+            }
+            CompileError::EmptyArrayLiteral { .. } => write!(
+                f,
+                "cannot infer the element type of an empty array, annotate it like `[i64; 0]`"
+            ),
+            CompileError::NotAddressable { .. } => write!(
+                f,
+                "cannot take the address of a temporary value, only of variables, their fields, \
+                 elements and dereferenced pointers"
+            ),
+            CompileError::UntypedNull { .. } => write!(
+                f,
+                "`null` needs a pointer type from its context, annotate it like \
+                 `let p: *i64 = null`"
+            ),
+            CompileError::DuplicateGlobal { name, .. } => {
+                write!(f, "constant or static `{}` is defined more than once", name)
+            }
+            CompileError::ImmutableAssignment { name, .. } => write!(
+                f,
+                "cannot assign to `{}`, only a `static mut` can be assigned to",
+                name
+            ),
+            CompileError::NonConstant { .. } => {
+                write!(f, "this expression cannot be evaluated at compile time")
+            }
+            CompileError::ConstantOverflow { .. } => {
+                write!(f, "evaluating this constant expression overflows")
+            }
+            CompileError::DivisionByZero { .. } => {
+                write!(f, "evaluating this constant expression divides by zero")
+            }
+            CompileError::RecursiveConstant { name, .. } => {
+                write!(f, "constant `{}` is defined in terms of itself", name)
+            }
+            CompileError::DuplicateBinding { name, .. } => {
+                write!(f, "`{}` is bound more than once in the same pattern", name)
+            }
+            CompileError::NonExhaustiveMatch { pattern, .. } => {
+                write!(f, "non-exhaustive match: `{}` is not covered", pattern)
+            }
+            CompileError::UnreachablePattern { .. } => {
+                write!(
+                    f,
+                    "unreachable pattern: earlier arms match every value it matches"
+                )
+            }
+            CompileError::UnknownType { name, .. } => write!(f, "unknown type `{}`", name),
+            CompileError::TypeMismatch {
+                expected, found, ..
+            } => write!(
+                f,
+                "mismatched types: expected `{}`, found `{}`",
+                expected, found
+            ),
+            CompileError::InvalidOperand { operator, ty, .. } => {
+                write!(f, "cannot apply `{}` to a value of type `{}`", operator, ty)
+            }
+            CompileError::InvalidCast { from, to, .. } => {
+                write!(f, "cannot cast `{}` as `{}`", from, to)
+            }
+            CompileError::LiteralOutOfRange { ty, .. } => {
+                write!(f, "literal out of range for `{}`", ty)
+            }
+            CompileError::LoopControlOutsideLoop { keyword, .. } => {
+                write!(f, "`{}` outside of a loop", keyword)
+            }
+            CompileError::MissingReturn { name, ty, .. } => write!(
+                f,
+                "missing return: function `{}` must return a value of type `{}`",
+                name, ty
+            ),
+            CompileError::InvalidExternReturn { ty, .. } => {
+                write!(f, "extern functions cannot return `{}`", ty)
+            }
+            CompileError::InvalidExternParameter { ty, .. } => {
+                write!(f, "extern functions cannot take a `{}`", ty)
+            }
+            CompileError::VerificationFailed { name, .. } => {
+                write!(f, "function `{}` failed LLVM verification", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CompileError {}
@@ -0,0 +1,417 @@
+//! Checks the arms of a `match` with the usefulness algorithm of Maranget,
+//! "Warnings for pattern matching". A pattern is useful after a list of
+//! earlier patterns when some value matches it but none of them. An arm whose
+//! pattern is not useful is unreachable, and a `match` is exhaustive when a
+//! wildcard after the last arm would not be useful.
+
+use std::collections::HashMap;
+
+use crate::{
+    diagnostics::Span,
+    error::CompileError,
+    internal_representations::ast::{self, PatternKind, Type},
+};
+
+type CheckResult<T> = Result<T, CompileError>;
+
+/// The shape of a value a pattern tests for.
+#[derive(Debug, Clone, PartialEq)]
+enum Constructor {
+    Variant(usize),
+    Bool(bool),
+    /// Bits of an integer or the code point of a character.
+    Value(u64),
+}
+
+/// Pattern reduced to what matters for exhaustiveness, bindings are
+/// wildcards as well.
+#[derive(Debug, Clone)]
+enum Pat {
+    Wild,
+    Constructor(Constructor, Vec<Pat>),
+}
+
+impl Pat {
+    fn from_pattern(pattern: &ast::Pattern) -> Pat {
+        match &pattern.kind {
+            PatternKind::Wildcard | PatternKind::Binding(_) => Pat::Wild,
+            PatternKind::Literal(literal) => {
+                let constructor = match literal.kind {
+                    ast::ExprKind::BoolLiteral(boolean) => Constructor::Bool(boolean),
+                    ast::ExprKind::IntLiteral(bits) => Constructor::Value(bits),
+                    ast::ExprKind::CharLiteral(char) => Constructor::Value(char as u64),
+                    _ => unreachable!("{:?} is not a literal pattern", literal),
+                };
+                Pat::Constructor(constructor, Vec::new())
+            }
+            PatternKind::Variant(index, fields) => Pat::Constructor(
+                Constructor::Variant(*index),
+                fields.iter().map(Pat::from_pattern).collect(),
+            ),
+        }
+    }
+
+    fn constructor(&self) -> Option<&Constructor> {
+        match self {
+            Pat::Wild => None,
+            Pat::Constructor(constructor, _) => Some(constructor),
+        }
+    }
+}
+
+struct Checker<'a> {
+    enums: &'a HashMap<String, ast::Enum>,
+}
+
+/// Rows of a matrix whose first pattern matches the constructor, with that
+/// pattern replaced by the patterns of its fields.
+fn specialize(matrix: &[Vec<Pat>], constructor: &Constructor, arity: usize) -> Vec<Vec<Pat>> {
+    matrix
+        .iter()
+        .filter_map(|row| {
+            let fields = match &row[0] {
+                Pat::Wild => vec![Pat::Wild; arity],
+                Pat::Constructor(other, fields) if other == constructor => fields.clone(),
+                Pat::Constructor(..) => return None,
+            };
+            Some(fields.into_iter().chain(row[1..].iter().cloned()).collect())
+        })
+        .collect()
+}
+
+/// Rows of a matrix starting with a wildcard, without it. They are the rows
+/// matching a value whose constructor no row names.
+fn default_rows(matrix: &[Vec<Pat>]) -> Vec<Vec<Pat>> {
+    matrix
+        .iter()
+        .filter(|row| matches!(row[0], Pat::Wild))
+        .map(|row| row[1..].to_vec())
+        .collect()
+}
+
+impl<'a> Checker<'a> {
+    /// Every constructor of a type, or `None` for integers, characters and
+    /// the other types that only a wildcard covers.
+    fn constructors(&self, ty: &Type) -> Option<Vec<Constructor>> {
+        match ty {
+            Type::Bool => Some(vec![Constructor::Bool(false), Constructor::Bool(true)]),
+            Type::Enum(name) => Some(
+                (0..self.enums[name].variants.len())
+                    .map(Constructor::Variant)
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
+
+    fn field_types(&self, ty: &Type, constructor: &Constructor) -> Vec<Type> {
+        match (ty, constructor) {
+            (Type::Enum(name), Constructor::Variant(index)) => {
+                self.enums[name].variants[*index].fields.clone()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Every constructor of the type when the first column of the matrix
+    /// names all of them.
+    fn complete_constructors(&self, matrix: &[Vec<Pat>], ty: &Type) -> Option<Vec<Constructor>> {
+        self.constructors(ty).filter(|constructors| {
+            constructors.iter().all(|constructor| {
+                matrix
+                    .iter()
+                    .any(|row| row[0].constructor() == Some(constructor))
+            })
+        })
+    }
+
+    /// Whether a value of the types `tys` matches `row` but no row of the
+    /// matrix.
+    fn is_useful(&self, matrix: &[Vec<Pat>], row: &[Pat], tys: &[Type]) -> bool {
+        let (first, rest) = match row.split_first() {
+            Some(split) => split,
+            None => return matrix.is_empty(),
+        };
+
+        let specialized = |constructor: &Constructor, fields: Vec<Pat>| {
+            let field_types = self.field_types(&tys[0], constructor);
+            let matrix = specialize(matrix, constructor, field_types.len());
+            let row: Vec<Pat> = fields.into_iter().chain(rest.iter().cloned()).collect();
+            let tys: Vec<Type> = field_types
+                .into_iter()
+                .chain(tys[1..].iter().cloned())
+                .collect();
+            self.is_useful(&matrix, &row, &tys)
+        };
+
+        match first {
+            Pat::Constructor(constructor, fields) => specialized(constructor, fields.clone()),
+            Pat::Wild => match self.complete_constructors(matrix, &tys[0]) {
+                Some(constructors) => constructors.iter().any(|constructor| {
+                    let arity = self.field_types(&tys[0], constructor).len();
+                    specialized(constructor, vec![Pat::Wild; arity])
+                }),
+                None => self.is_useful(&default_rows(matrix), rest, &tys[1..]),
+            },
+        }
+    }
+
+    /// Patterns for the types `tys` that match a value no row of the matrix
+    /// matches, if there is such a value.
+    fn missing(&self, matrix: &[Vec<Pat>], tys: &[Type]) -> Option<Vec<Pat>> {
+        let ty = match tys.first() {
+            Some(ty) => ty,
+            None => return matrix.is_empty().then(Vec::new),
+        };
+
+        match self.complete_constructors(matrix, ty) {
+            Some(constructors) => constructors.into_iter().find_map(|constructor| {
+                let field_types = self.field_types(ty, &constructor);
+                let arity = field_types.len();
+                let matrix = specialize(matrix, &constructor, arity);
+                let tys: Vec<Type> = field_types
+                    .into_iter()
+                    .chain(tys[1..].iter().cloned())
+                    .collect();
+
+                let mut witness = self.missing(&matrix, &tys)?;
+                let rest = witness.split_off(arity);
+                Some(
+                    std::iter::once(Pat::Constructor(constructor, witness))
+                        .chain(rest)
+                        .collect(),
+                )
+            }),
+            None => {
+                let rest = self.missing(&default_rows(matrix), &tys[1..])?;
+
+                // name a constructor missing from the first column when the
+                // type has a finite number of them, `_` otherwise
+                let first = self
+                    .constructors(ty)
+                    .and_then(|constructors| {
+                        constructors.into_iter().find(|constructor| {
+                            matrix
+                                .iter()
+                                .all(|row| row[0].constructor() != Some(constructor))
+                        })
+                    })
+                    .map_or(Pat::Wild, |constructor| {
+                        let arity = self.field_types(ty, &constructor).len();
+                        Pat::Constructor(constructor, vec![Pat::Wild; arity])
+                    });
+
+                Some(std::iter::once(first).chain(rest).collect())
+            }
+        }
+    }
+
+    /// Writes a pattern the way it would appear in the source.
+    fn display(&self, pat: &Pat, ty: &Type) -> String {
+        let (constructor, fields) = match pat {
+            Pat::Wild => return "_".to_string(),
+            Pat::Constructor(constructor, fields) => (constructor, fields),
+        };
+
+        match (constructor, ty) {
+            (Constructor::Variant(index), Type::Enum(name)) => {
+                let variant = &self.enums[name].variants[*index];
+                let mut text = format!("{}::{}", name, variant.name);
+
+                if !fields.is_empty() {
+                    let fields: Vec<String> = fields
+                        .iter()
+                        .zip(&variant.fields)
+                        .map(|(field, ty)| self.display(field, ty))
+                        .collect();
+                    text = format!("{}({})", text, fields.join(", "));
+                }
+
+                text
+            }
+            (Constructor::Bool(boolean), _) => boolean.to_string(),
+            (Constructor::Value(value), Type::Char) => {
+                let char = char::from_u32(*value as u32).unwrap_or(char::REPLACEMENT_CHARACTER);
+                format!("{:?}", char)
+            }
+            (Constructor::Value(value), _) => value.to_string(),
+            (Constructor::Variant(_), _) => unreachable!("variant of a non-enum type {}", ty),
+        }
+    }
+}
+
+/// Rejects arms of a `match` that can never be reached, and a `match` that
+/// leaves some values of the type `ty` without an arm.
+pub fn check_match(
+    enums: &HashMap<String, ast::Enum>,
+    ty: &Type,
+    arms: &[ast::Arm],
+    span: Span,
+) -> CheckResult<()> {
+    let checker = Checker { enums };
+    let tys = [ty.clone()];
+    let mut matrix = Vec::with_capacity(arms.len());
+
+    for arm in arms {
+        let row = vec![Pat::from_pattern(&arm.pattern)];
+
+        if !checker.is_useful(&matrix, &row, &tys) {
+            return Err(CompileError::UnreachablePattern {
+                span: arm.pattern.span,
+            });
+        }
+
+        matrix.push(row);
+    }
+
+    match checker.missing(&matrix, &tys) {
+        Some(witness) => Err(CompileError::NonExhaustiveMatch {
+            pattern: checker.display(&witness[0], ty),
+            span,
+        }),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `enum E { A, B(bool) }`
+    fn enums() -> HashMap<String, ast::Enum> {
+        let def = ast::Enum {
+            name: "E".to_string(),
+            variants: vec![
+                ast::Variant {
+                    name: "A".to_string(),
+                    fields: Vec::new(),
+                },
+                ast::Variant {
+                    name: "B".to_string(),
+                    fields: vec![Type::Bool],
+                },
+            ],
+            span: Span::default(),
+        };
+
+        HashMap::from([("E".to_string(), def)])
+    }
+
+    fn enum_type() -> Type {
+        Type::Enum("E".to_string())
+    }
+
+    fn pattern(kind: PatternKind, ty: Type) -> ast::Pattern {
+        ast::Pattern {
+            kind,
+            ty,
+            span: Span::default(),
+        }
+    }
+
+    fn wild(ty: Type) -> ast::Pattern {
+        pattern(PatternKind::Wildcard, ty)
+    }
+
+    fn literal(kind: ast::ExprKind, ty: Type) -> ast::Pattern {
+        let literal = ast::Expr::new(kind, ty.clone(), Span::default());
+        pattern(PatternKind::Literal(literal), ty)
+    }
+
+    fn boolean(value: bool) -> ast::Pattern {
+        literal(ast::ExprKind::BoolLiteral(value), Type::Bool)
+    }
+
+    fn variant(index: usize, fields: Vec<ast::Pattern>) -> ast::Pattern {
+        pattern(PatternKind::Variant(index, fields), enum_type())
+    }
+
+    /// Checks a `match` with an arm for each pattern. The pattern of the
+    /// arm at index `i` starts at `i`, so errors tell which arm they are for.
+    fn check(ty: Type, patterns: Vec<ast::Pattern>) -> CheckResult<()> {
+        let arms: Vec<ast::Arm> = patterns
+            .into_iter()
+            .enumerate()
+            .map(|(i, pattern)| ast::Arm {
+                pattern: ast::Pattern {
+                    span: Span::new(i, i + 1),
+                    ..pattern
+                },
+                body: Vec::new(),
+                value: None,
+            })
+            .collect();
+
+        check_match(&enums(), &ty, &arms, Span::default())
+    }
+
+    fn missing(ty: Type, patterns: Vec<ast::Pattern>) -> String {
+        match check(ty, patterns) {
+            Err(CompileError::NonExhaustiveMatch { pattern, .. }) => pattern,
+            result => panic!("expected a missing pattern, found {:?}", result),
+        }
+    }
+
+    fn unreachable_arm(ty: Type, patterns: Vec<ast::Pattern>) -> usize {
+        match check(ty, patterns) {
+            Err(CompileError::UnreachablePattern { span }) => span.start,
+            result => panic!("expected an unreachable arm, found {:?}", result),
+        }
+    }
+
+    #[test]
+    fn bool_scrutinee() {
+        assert_eq!(
+            check(Type::Bool, vec![boolean(true), boolean(false)]),
+            Ok(())
+        );
+        assert_eq!(missing(Type::Bool, vec![boolean(true)]), "false");
+        assert_eq!(
+            unreachable_arm(
+                Type::Bool,
+                vec![boolean(true), boolean(false), wild(Type::Bool)]
+            ),
+            2
+        );
+    }
+
+    #[test]
+    fn enum_scrutinee() {
+        let arms = vec![variant(0, Vec::new()), variant(1, vec![wild(Type::Bool)])];
+        assert_eq!(check(enum_type(), arms), Ok(()));
+
+        assert_eq!(
+            missing(enum_type(), vec![variant(1, vec![wild(Type::Bool)])]),
+            "E::A"
+        );
+        assert_eq!(missing(enum_type(), Vec::new()), "E::A");
+    }
+
+    #[test]
+    fn missing_variant_field() {
+        let arms = vec![variant(0, Vec::new()), variant(1, vec![boolean(true)])];
+        assert_eq!(missing(enum_type(), arms), "E::B(false)");
+    }
+
+    #[test]
+    fn unreachable_arms() {
+        let arms = vec![wild(enum_type()), variant(0, Vec::new())];
+        assert_eq!(unreachable_arm(enum_type(), arms), 1);
+
+        let arms = vec![
+            variant(1, vec![wild(Type::Bool)]),
+            variant(1, vec![boolean(true)]),
+            wild(enum_type()),
+        ];
+        assert_eq!(unreachable_arm(enum_type(), arms), 1);
+    }
+
+    #[test]
+    fn integer_scrutinee() {
+        let int = |value| literal(ast::ExprKind::IntLiteral(value), Type::I64);
+
+        assert_eq!(missing(Type::I64, vec![int(0), int(1)]), "_");
+        assert_eq!(check(Type::I64, vec![int(0), wild(Type::I64)]), Ok(()));
+        assert_eq!(unreachable_arm(Type::I64, vec![int(3), int(3)]), 1);
+    }
+}
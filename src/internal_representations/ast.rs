@@ -1 +1,386 @@
+//! Resolved and typed tree lowered from the gast. Every variable refers to a
+//! unique binding instead of a name, every expression carries its type, and
+//! `for`, `while` and `loop` are all lowered to a single kind of loop.
 
+use std::fmt;
+
+use crate::diagnostics::Span;
+
+pub use super::gast::{LogicalOperator, Operator, UnaryOperator};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BindingId(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntType {
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+}
+
+impl IntType {
+    pub fn bits(self) -> u32 {
+        match self {
+            IntType::I8 | IntType::U8 => 8,
+            IntType::I16 | IntType::U16 => 16,
+            IntType::I32 | IntType::U32 => 32,
+            IntType::I64 | IntType::U64 => 64,
+        }
+    }
+
+    pub fn is_signed(self) -> bool {
+        matches!(
+            self,
+            IntType::I8 | IntType::I16 | IntType::I32 | IntType::I64
+        )
+    }
+
+    /// Largest value of the type.
+    pub fn max(self) -> u64 {
+        let bits = if self.is_signed() {
+            self.bits() - 1
+        } else {
+            self.bits()
+        };
+
+        u64::MAX >> (64 - bits)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FloatType {
+    F32,
+    F64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Int(IntType),
+    Float(FloatType),
+    Bool,
+    /// Unicode scalar value, stored as its `u32` code point.
+    Char,
+    Str,
+    Unit,
+    /// Struct declared in the program, referred to by its name.
+    Struct(String),
+    Enum(String),
+    /// Fixed number of elements stored inline.
+    Array(Box<Type>, u64),
+    /// Pointer to elements stored elsewhere and their number.
+    Slice(Box<Type>),
+    /// Address of a value of the type, or null.
+    Pointer(Box<Type>),
+}
+
+impl Type {
+    pub const I64: Type = Type::Int(IntType::I64);
+    pub const F64: Type = Type::Float(FloatType::F64);
+
+    /// Primitive type spelled by a type name in the source.
+    pub fn from_name(name: &str) -> Option<Type> {
+        Some(match name {
+            "i8" => Type::Int(IntType::I8),
+            "i16" => Type::Int(IntType::I16),
+            "i32" => Type::Int(IntType::I32),
+            "i64" => Type::Int(IntType::I64),
+            "u8" => Type::Int(IntType::U8),
+            "u16" => Type::Int(IntType::U16),
+            "u32" => Type::Int(IntType::U32),
+            "u64" => Type::Int(IntType::U64),
+            "f32" => Type::Float(FloatType::F32),
+            "f64" => Type::Float(FloatType::F64),
+            "bool" => Type::Bool,
+            "char" => Type::Char,
+            "str" => Type::Str,
+            "unit" => Type::Unit,
+            _ => return None,
+        })
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, Type::Int(_) | Type::Float(_))
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, Type::Float(_))
+    }
+
+    /// Whether the type is a struct, an enum or an array, which are passed
+    /// by reference.
+    pub fn is_aggregate(&self) -> bool {
+        matches!(self, Type::Struct(_) | Type::Enum(_) | Type::Array(..))
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Type::Int(IntType::I8) => "i8",
+            Type::Int(IntType::I16) => "i16",
+            Type::Int(IntType::I32) => "i32",
+            Type::Int(IntType::I64) => "i64",
+            Type::Int(IntType::U8) => "u8",
+            Type::Int(IntType::U16) => "u16",
+            Type::Int(IntType::U32) => "u32",
+            Type::Int(IntType::U64) => "u64",
+            Type::Float(FloatType::F32) => "f32",
+            Type::Float(FloatType::F64) => "f64",
+            Type::Bool => "bool",
+            Type::Char => "char",
+            Type::Str => "str",
+            Type::Unit => "unit",
+            Type::Struct(name) | Type::Enum(name) => name.as_str(),
+            Type::Array(element, len) => return write!(f, "[{}; {}]", element, len),
+            Type::Slice(element) => return write!(f, "[{}]", element),
+            Type::Pointer(pointee) => return write!(f, "*{}", pointee),
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Binding {
+    pub name: String,
+    pub ty: Type,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
+    /// Value of the literal as a 64 bit two's complement integer, so the
+    /// value of a negative literal is sign extended beyond its type.
+    IntLiteral(u64),
+    FloatLiteral(f64),
+    BoolLiteral(bool),
+    CharLiteral(char),
+    StringLiteral(String),
+    /// Null pointer of the type of the expression.
+    Null,
+    Variable(BindingId),
+    Unary(UnaryOperator, Box<Expr>),
+    /// Pointer to a place, see `ExprKind::is_place`.
+    AddressOf(Box<Expr>),
+    /// Value the pointer points to.
+    Deref(Box<Expr>),
+    Binary(Operator, Box<Expr>, Box<Expr>),
+    Logical(LogicalOperator, Box<Expr>, Box<Expr>),
+    Cast(Box<Expr>),
+    Call(String, Vec<Expr>),
+    /// Index of every field and its value, in the order they are evaluated.
+    StructLiteral(Vec<(usize, Expr)>),
+    Field(Box<Expr>, usize),
+    /// Index of the variant and the values of its fields.
+    Variant(usize, Vec<Expr>),
+    Match(Box<Expr>, Vec<Arm>),
+    ArrayLiteral(Vec<Expr>),
+    /// Element of an array or a slice, the index is an `i64`.
+    Index(Box<Expr>, Box<Expr>),
+    /// Slice of every element of an array.
+    AsSlice(Box<Expr>),
+    /// Number of elements of an array or a slice, or of bytes of a `str`.
+    Len(Box<Expr>),
+    /// Moves the value to a new heap allocation and yields a pointer to it.
+    Alloc(Box<Expr>),
+    /// Releases the heap allocation the pointer points to.
+    Free(Box<Expr>),
+}
+
+impl ExprKind {
+    /// Whether the expression denotes a memory location that can be
+    /// assigned to.
+    pub fn is_place(&self) -> bool {
+        match self {
+            ExprKind::Variable(_) => true,
+            ExprKind::Field(base, _) => base.kind.is_place(),
+            // the elements of a slice are always stored in memory
+            ExprKind::Index(base, _) => matches!(base.ty, Type::Slice(_)) || base.kind.is_place(),
+            ExprKind::Deref(_) => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub ty: Type,
+    pub span: Span,
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, ty: Type, span: Span) -> Self {
+        Expr { kind, ty, span }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatternKind {
+    Wildcard,
+    Binding(BindingId),
+    /// Integer, boolean or character literal.
+    Literal(Expr),
+    Variant(usize, Vec<Pattern>),
+}
+
+/// Pattern matching values of the type `ty`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    pub kind: PatternKind,
+    pub ty: Type,
+    pub span: Span,
+}
+
+impl Pattern {
+    /// Whether the pattern matches every value of its type.
+    pub fn is_irrefutable(&self) -> bool {
+        matches!(self.kind, PatternKind::Wildcard | PatternKind::Binding(_))
+    }
+}
+
+/// Arm of a `match`. The value is `None` for arms with a block, which
+/// produce `unit` or never finish.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Arm {
+    pub pattern: Pattern,
+    pub body: Vec<Stmt>,
+    pub value: Option<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StmtKind {
+    Expr(Expr),
+    Let(BindingId, Expr),
+    /// Stores the value in a place, see `ExprKind::is_place`.
+    Assign(Expr, Expr),
+    Return(Option<Expr>),
+    If(Expr, Vec<Stmt>, Option<Vec<Stmt>>),
+    /// Runs the body while the condition holds, or forever without one. The
+    /// step runs after every iteration, also after a `continue`.
+    Loop(Option<Expr>, Vec<Stmt>, Vec<Stmt>),
+    Break,
+    Continue,
+    Block(Vec<Stmt>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+impl Stmt {
+    pub fn new(kind: StmtKind, span: Span) -> Self {
+        Stmt { kind, span }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<BindingId>,
+    pub ret: Type,
+    pub body: Vec<Stmt>,
+    pub span: Span,
+}
+
+/// Function declared with `extern fun`. It follows the C calling convention,
+/// so `str` arguments are passed as a pointer to their bytes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExternFunction {
+    pub name: String,
+    pub params: Vec<Type>,
+    pub variadic: bool,
+    pub ret: Type,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub name: String,
+    pub ty: Type,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Struct {
+    pub name: String,
+    pub fields: Vec<Field>,
+    pub span: Span,
+}
+
+impl Struct {
+    /// Index and declaration of the field with the given name.
+    pub fn field(&self, name: &str) -> Option<(usize, &Field)> {
+        self.fields
+            .iter()
+            .enumerate()
+            .find(|(_, field)| field.name == name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variant {
+    pub name: String,
+    pub fields: Vec<Type>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Enum {
+    pub name: String,
+    pub variants: Vec<Variant>,
+    pub span: Span,
+}
+
+impl Enum {
+    /// Index and declaration of the variant with the given name.
+    pub fn variant(&self, name: &str) -> Option<(usize, &Variant)> {
+        self.variants
+            .iter()
+            .enumerate()
+            .find(|(_, variant)| variant.name == name)
+    }
+}
+
+/// Global variable, its initial value is computed by the compiler, see
+/// `const_eval`. Constants have no node, they are inlined where used.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Static {
+    pub id: BindingId,
+    pub mutable: bool,
+    pub value: Expr,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub functions: Vec<Function>,
+    pub externs: Vec<ExternFunction>,
+    pub structs: Vec<Struct>,
+    pub enums: Vec<Enum>,
+    pub statics: Vec<Static>,
+    pub bindings: Vec<Binding>,
+}
+
+impl Program {
+    pub fn binding(&self, id: BindingId) -> &Binding {
+        &self.bindings[id.0]
+    }
+
+    pub fn extern_function(&self, name: &str) -> Option<&ExternFunction> {
+        self.externs.iter().find(|function| function.name == name)
+    }
+
+    pub fn struct_def(&self, name: &str) -> Option<&Struct> {
+        self.structs.iter().find(|def| def.name == name)
+    }
+
+    pub fn enum_def(&self, name: &str) -> Option<&Enum> {
+        self.enums.iter().find(|def| def.name == name)
+    }
+}
@@ -1,71 +1,289 @@
+use crate::{diagnostics::Span, internal_representations::ast::IntType};
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Name {
     pub name: String,
+    pub span: Span,
 }
 
 impl Name {
-    pub fn new(name: String) -> Self {
-        Name { name }
+    pub fn new(name: String, span: Span) -> Self {
+        Name { name, span }
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeNameKind {
+    Named(Name),
+    /// `[i64; 10]`, the type of the elements and their number.
+    Array(Box<TypeName>, u64),
+    /// `[i64]`
+    Slice(Box<TypeName>),
+    /// `*i64`
+    Pointer(Box<TypeName>),
+}
+
+/// Type as written in the source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeName {
+    pub kind: TypeNameKind,
+    pub span: Span,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Operator {
     Add,
     Sub,
     Mul,
     Div,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Operator {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Operator::Add => "+",
+            Operator::Sub => "-",
+            Operator::Mul => "*",
+            Operator::Div => "/",
+            Operator::Eq => "==",
+            Operator::Ne => "!=",
+            Operator::Lt => "<",
+            Operator::Le => "<=",
+            Operator::Gt => ">",
+            Operator::Ge => ">=",
+        }
+    }
+
+    pub fn is_comparison(&self) -> bool {
+        !matches!(
+            self,
+            Operator::Add | Operator::Sub | Operator::Mul | Operator::Div
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LogicalOperator {
+    And,
+    Or,
+}
+
+impl LogicalOperator {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            LogicalOperator::And => "&&",
+            LogicalOperator::Or => "||",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UnaryOperator {
+    Neg,
+    Not,
+}
+
+impl UnaryOperator {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            UnaryOperator::Neg => "-",
+            UnaryOperator::Not => "!",
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub enum Expr {
-    IntLiteral(i64),
+pub enum ExprKind {
+    /// Value of an integer literal and the type of its suffix, like `u8` in
+    /// `10u8`. The value is negated by an enclosing `Neg`.
+    IntLiteral(u64, Option<IntType>),
+    FloatLiteral(f64),
+    BoolLiteral(bool),
+    CharLiteral(char),
     StringLiteral(String),
+    /// Pointer to nothing, its type is taken from the context.
+    Null,
     Name(Name),
+    Unary(UnaryOperator, Box<Expr>),
+    /// `&x`, a pointer to the place.
+    AddressOf(Box<Expr>),
+    /// `*p`, the place the pointer points to.
+    Deref(Box<Expr>),
     Binary(Operator, Box<Expr>, Box<Expr>),
+    Logical(LogicalOperator, Box<Expr>, Box<Expr>),
+    Cast(Box<Expr>, TypeName),
     Call(Name, Vec<Expr>),
+    /// `Point { x: 1, y: 2 }`, with the fields in the order they are written.
+    StructLiteral(Name, Vec<(Name, Expr)>),
+    Field(Box<Expr>, Name),
+    /// `Shape::Circle(1.0)`, the name of the enum, the variant and its values.
+    Variant(Name, Name, Vec<Expr>),
+    Match(Box<Expr>, Vec<Arm>),
+    ArrayLiteral(Vec<Expr>),
+    /// `a[i]`, the indexed array or slice and the index.
+    Index(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
 }
 
 impl Expr {
-    #[allow(clippy::should_implement_trait)]
-    pub fn add(left: Expr, right: Expr) -> Self {
-        Expr::Binary(Operator::Add, Box::new(left), Box::new(right))
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Expr { kind, span }
     }
+}
 
-    #[allow(clippy::should_implement_trait)]
-    pub fn sub(left: Expr, right: Expr) -> Self {
-        Expr::Binary(Operator::Sub, Box::new(left), Box::new(right))
-    }
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatternKind {
+    Wildcard,
+    Binding(Name),
+    /// Integer, boolean or character literal, possibly negated.
+    Literal(Expr),
+    Variant(Name, Name, Vec<Pattern>),
+}
 
-    #[allow(clippy::should_implement_trait)]
-    pub fn mul(left: Expr, right: Expr) -> Self {
-        Expr::Binary(Operator::Mul, Box::new(left), Box::new(right))
-    }
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    pub kind: PatternKind,
+    pub span: Span,
+}
 
-    #[allow(clippy::should_implement_trait)]
-    pub fn div(left: Expr, right: Expr) -> Self {
-        Expr::Binary(Operator::Div, Box::new(left), Box::new(right))
-    }
+/// Arm of a `match`. Its body is either a block or a single expression,
+/// whose value is the value of the `match`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Arm {
+    pub pattern: Pattern,
+    pub body: Vec<Stmt>,
+    pub value: Option<Expr>,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub enum Stmt {
+pub enum StmtKind {
     Expr(Expr),
-    Let(Name, Expr),
-    Return(Expr),
-    If(Expr, Vec<Stmt>, Vec<Stmt>),
-    For(Box<Stmt>, Expr, Expr, Vec<Stmt>),
-    Assign(Name, Box<Expr>),
+    Let(Name, Option<TypeName>, Expr),
+    Return(Option<Expr>),
+    If(Expr, Vec<Stmt>, Option<Vec<Stmt>>),
+    For(Box<Stmt>, Expr, Box<Stmt>, Vec<Stmt>),
+    While(Expr, Vec<Stmt>),
+    Loop(Vec<Stmt>),
+    Break,
+    Continue,
+    /// Stores the value in the place on the left, a variable, a field, an
+    /// element or a dereferenced pointer, which the type checker verifies.
+    Assign(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+impl Stmt {
+    pub fn new(kind: StmtKind, span: Span) -> Self {
+        Stmt { kind, span }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub name: Name,
+    pub ty: TypeName,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Function {
+    /// Text of the `///` comments in front of the function.
+    pub doc: Option<String>,
     pub name: Name,
-    pub args: Vec<Name>,
+    pub args: Vec<Param>,
+    pub ret: Option<TypeName>,
     pub body: Vec<Stmt>,
+    /// Span of the first syntax error in the body, if the parser left out
+    /// statements of it.
+    pub skipped: Option<Span>,
+    pub span: Span,
+}
+
+/// Function defined outside of the program, like one of libc. A variadic
+/// function accepts any number of arguments after its parameters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExternFunction {
+    pub doc: Option<String>,
+    pub name: Name,
+    pub args: Vec<Param>,
+    pub variadic: bool,
+    pub ret: Option<TypeName>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub name: Name,
+    pub ty: TypeName,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Struct {
+    pub doc: Option<String>,
+    pub name: Name,
+    pub fields: Vec<Field>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variant {
+    pub name: Name,
+    pub fields: Vec<TypeName>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Enum {
+    pub doc: Option<String>,
+    pub name: Name,
+    pub variants: Vec<Variant>,
+    pub span: Span,
+}
+
+/// `const MAX = 100;`, a value computed by the compiler and inlined
+/// wherever the constant is used.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Const {
+    pub doc: Option<String>,
+    pub name: Name,
+    pub ty: Option<TypeName>,
+    pub value: Expr,
+    pub span: Span,
+}
+
+/// `static mut counter: i64 = 0;`, a global variable. Only a `static mut`
+/// can be assigned to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Static {
+    pub doc: Option<String>,
+    pub name: Name,
+    pub mutable: bool,
+    pub ty: TypeName,
+    pub value: Expr,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Program {
     pub functions: Vec<Function>,
+    pub externs: Vec<ExternFunction>,
+    pub structs: Vec<Struct>,
+    pub enums: Vec<Enum>,
+    pub consts: Vec<Const>,
+    pub statics: Vec<Static>,
 }
@@ -0,0 +1,1361 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    builtins::Builtin,
+    const_eval::evaluate,
+    diagnostics::Span,
+    error::CompileError,
+    exhaustiveness::check_match,
+    internal_representations::{
+        ast::{self, Binding, BindingId, IntType, Type},
+        gast::{
+            self, ExprKind, Name, Operator, PatternKind, StmtKind, TypeName, TypeNameKind,
+            UnaryOperator,
+        },
+    },
+    resolver::SymbolTable,
+};
+
+type LowerResult<T> = Result<T, CompileError>;
+
+struct Signature {
+    params: Vec<Type>,
+    variadic: bool,
+    ret: Type,
+}
+
+/// Lowers the gast into the typed ast and type checks it on the way. Names
+/// are looked up in the symbol table of the resolver, so the program must
+/// have resolved without errors.
+struct Lowering<'a> {
+    symbols: &'a SymbolTable,
+    structs: HashMap<String, ast::Struct>,
+    enums: HashMap<String, ast::Enum>,
+    functions: HashMap<String, Signature>,
+    types: HashMap<BindingId, Type>,
+    ret: Type,
+    /// Declarations of the constants, evaluated when they are first used.
+    consts: HashMap<BindingId, &'a gast::Const>,
+    const_values: HashMap<BindingId, ast::Expr>,
+    /// Constants whose initializers are being evaluated, to report a
+    /// constant that depends on itself.
+    evaluating: Vec<BindingId>,
+}
+
+fn expect_type(expr: &ast::Expr, expected: &Type) -> LowerResult<()> {
+    if expr.ty == *expected {
+        Ok(())
+    } else {
+        Err(CompileError::TypeMismatch {
+            expected: expected.clone(),
+            found: expr.ty.clone(),
+            span: expr.span,
+        })
+    }
+}
+
+/// Numeric literals take their type from the context they are used in, so
+/// `let x: u8 = 1`, `let y: f32 = 1.5` and `x + 1` need no casts. Integer
+/// literals with a suffix, like `1u8`, have a fixed type instead. `null`
+/// takes its type from the context as well, as in `null == p`.
+fn is_literal(expr: &gast::Expr) -> bool {
+    match &expr.kind {
+        ExprKind::IntLiteral(_, None) | ExprKind::FloatLiteral(_) | ExprKind::Null => true,
+        ExprKind::Unary(UnaryOperator::Neg, operand) => is_literal(operand),
+        _ => false,
+    }
+}
+
+/// Types an integer literal, negated if it is the operand of a `-`, and
+/// checks that its value fits the type.
+fn lower_int_literal(
+    value: u64,
+    suffix: Option<IntType>,
+    negative: bool,
+    expected: Option<&Type>,
+    span: Span,
+) -> LowerResult<ast::Expr> {
+    let int = match (suffix, expected) {
+        (Some(int), _) | (None, Some(&Type::Int(int))) => int,
+        _ => IntType::I64,
+    };
+    let ty = Type::Int(int);
+
+    if negative && !int.is_signed() {
+        return Err(CompileError::InvalidOperand {
+            operator: UnaryOperator::Neg.symbol().to_string(),
+            ty,
+            span,
+        });
+    }
+
+    // the magnitude of the smallest signed value is one more than the largest
+    let max = if negative { int.max() + 1 } else { int.max() };
+    if value > max {
+        return Err(CompileError::LiteralOutOfRange { ty, span });
+    }
+
+    let value = if negative {
+        value.wrapping_neg()
+    } else {
+        value
+    };
+    Ok(ast::Expr::new(ast::ExprKind::IntLiteral(value), ty, span))
+}
+
+fn is_valid_cast(from: &Type, to: &Type) -> bool {
+    from == to
+        || matches!(
+            (from, to),
+            (Type::Int(_), Type::Int(_))
+                | (Type::Int(_), Type::Float(_))
+                | (Type::Float(_), Type::Int(_))
+                | (Type::Float(_), Type::Float(_))
+                | (Type::Bool, Type::Int(_))
+                | (Type::Char, Type::Int(_))
+                | (Type::Int(IntType::U8), Type::Char)
+                | (Type::Pointer(_), Type::Pointer(_))
+        )
+}
+
+/// Wraps an expression in a cast, unless it already has the type.
+fn cast_to(expr: ast::Expr, ty: Type) -> ast::Expr {
+    if expr.ty == ty {
+        expr
+    } else {
+        let span = expr.span;
+        ast::Expr::new(ast::ExprKind::Cast(Box::new(expr)), ty, span)
+    }
+}
+
+/// Applies the C default argument promotions to an argument passed in the
+/// variadic part of an extern call.
+fn promote_variadic(arg: ast::Expr, function: &Name) -> LowerResult<ast::Expr> {
+    let ty = match arg.ty {
+        Type::Int(int) if int.bits() < 32 && int.is_signed() => Type::Int(IntType::I32),
+        Type::Int(int) if int.bits() < 32 => Type::Int(IntType::U32),
+        Type::Bool => Type::Int(IntType::I32),
+        Type::Float(_) => Type::F64,
+        Type::Unit | Type::Struct(_) | Type::Enum(_) | Type::Array(..) | Type::Slice(_) => {
+            return Err(invalid_operand(&function.name, &arg, arg.span))
+        }
+        _ => arg.ty.clone(),
+    };
+
+    Ok(cast_to(arg, ty))
+}
+
+/// Whether control never reaches the end of the block, because every path
+/// leaves it with a `return` or stays in a `loop` without `break`.
+fn always_returns(block: &[ast::Stmt]) -> bool {
+    block.iter().any(|stmt| match &stmt.kind {
+        ast::StmtKind::Return(_) => true,
+        ast::StmtKind::If(_, then_b, Some(else_b)) => {
+            always_returns(then_b) && always_returns(else_b)
+        }
+        ast::StmtKind::Block(block) => always_returns(block),
+        ast::StmtKind::Loop(None, body, _) => !breaks(body),
+        // a `match` covers every value, so one of its arms always runs
+        ast::StmtKind::Expr(ast::Expr {
+            kind: ast::ExprKind::Match(_, arms),
+            ..
+        }) => arms
+            .iter()
+            .all(|arm| arm.value.is_none() && always_returns(&arm.body)),
+        _ => false,
+    })
+}
+
+/// Whether the body of a loop contains a `break` leaving that loop, as
+/// opposed to one leaving a nested loop.
+fn breaks(block: &[ast::Stmt]) -> bool {
+    block.iter().any(|stmt| match &stmt.kind {
+        ast::StmtKind::Break => true,
+        ast::StmtKind::Expr(expr) | ast::StmtKind::Let(_, expr) => expr_breaks(expr),
+        ast::StmtKind::Return(expr) => expr.as_ref().is_some_and(expr_breaks),
+        ast::StmtKind::Assign(place, value) => expr_breaks(place) || expr_breaks(value),
+        ast::StmtKind::If(cond, then_b, else_b) => {
+            expr_breaks(cond) || breaks(then_b) || else_b.as_deref().is_some_and(breaks)
+        }
+        ast::StmtKind::Block(block) => breaks(block),
+        // the condition and the step of a nested loop run outside of it
+        ast::StmtKind::Loop(cond, _, step) => {
+            cond.as_ref().is_some_and(expr_breaks) || breaks(step)
+        }
+        ast::StmtKind::Continue => false,
+    })
+}
+
+/// Whether the blocks of a `match` in the expression contain a `break`.
+fn expr_breaks(expr: &ast::Expr) -> bool {
+    match &expr.kind {
+        ast::ExprKind::Match(scrutinee, arms) => {
+            expr_breaks(scrutinee)
+                || arms
+                    .iter()
+                    .any(|arm| breaks(&arm.body) || arm.value.as_ref().is_some_and(expr_breaks))
+        }
+        ast::ExprKind::Unary(_, operand)
+        | ast::ExprKind::Cast(operand)
+        | ast::ExprKind::Field(operand, _)
+        | ast::ExprKind::AsSlice(operand)
+        | ast::ExprKind::Len(operand)
+        | ast::ExprKind::AddressOf(operand)
+        | ast::ExprKind::Deref(operand)
+        | ast::ExprKind::Alloc(operand)
+        | ast::ExprKind::Free(operand) => expr_breaks(operand),
+        ast::ExprKind::Binary(_, lhs, rhs)
+        | ast::ExprKind::Logical(_, lhs, rhs)
+        | ast::ExprKind::Index(lhs, rhs) => expr_breaks(lhs) || expr_breaks(rhs),
+        ast::ExprKind::Call(_, values)
+        | ast::ExprKind::Variant(_, values)
+        | ast::ExprKind::ArrayLiteral(values) => values.iter().any(expr_breaks),
+        ast::ExprKind::StructLiteral(fields) => fields.iter().any(|(_, value)| expr_breaks(value)),
+        ast::ExprKind::IntLiteral(_)
+        | ast::ExprKind::FloatLiteral(_)
+        | ast::ExprKind::BoolLiteral(_)
+        | ast::ExprKind::CharLiteral(_)
+        | ast::ExprKind::StringLiteral(_)
+        | ast::ExprKind::Null
+        | ast::ExprKind::Variable(_) => false,
+    }
+}
+
+fn invalid_operand(operator: &str, operand: &ast::Expr, span: Span) -> CompileError {
+    CompileError::InvalidOperand {
+        operator: operator.to_string(),
+        ty: operand.ty.clone(),
+        span,
+    }
+}
+
+impl<'a> Lowering<'a> {
+    fn resolve_type(&self, ty: &TypeName) -> LowerResult<Type> {
+        match &ty.kind {
+            TypeNameKind::Named(name) => self.resolve_named_type(name),
+            TypeNameKind::Array(element, len) => {
+                Ok(Type::Array(Box::new(self.resolve_type(element)?), *len))
+            }
+            TypeNameKind::Slice(element) => Ok(Type::Slice(Box::new(self.resolve_type(element)?))),
+            TypeNameKind::Pointer(pointee) => {
+                Ok(Type::Pointer(Box::new(self.resolve_type(pointee)?)))
+            }
+        }
+    }
+
+    /// Type spelled by a single name, a primitive type, a struct or an enum.
+    fn resolve_named_type(&self, name: &Name) -> LowerResult<Type> {
+        match Type::from_name(&name.name) {
+            Some(ty) => Ok(ty),
+            None if self.structs.contains_key(&name.name) => Ok(Type::Struct(name.name.clone())),
+            None if self.enums.contains_key(&name.name) => Ok(Type::Enum(name.name.clone())),
+            None => Err(CompileError::UnknownType {
+                name: name.name.clone(),
+                span: name.span,
+            }),
+        }
+    }
+
+    /// Whether a value of the struct or enum `name` holds a value of the type
+    /// `target`, in one of its fields or in a field of a field.
+    fn contains_type(&self, name: &str, target: &str, visited: &mut HashSet<String>) -> bool {
+        let fields: Vec<&Type> = match self.structs.get(name) {
+            Some(def) => def.fields.iter().map(|field| &field.ty).collect(),
+            None => self.enums[name]
+                .variants
+                .iter()
+                .flat_map(|variant| &variant.fields)
+                .collect(),
+        };
+
+        fields.into_iter().any(|mut ty| {
+            // the elements of an array are stored inline, unlike those of a
+            // slice
+            while let Type::Array(element, _) = ty {
+                ty = element;
+            }
+
+            match ty {
+                Type::Struct(inner) | Type::Enum(inner) => {
+                    inner == target
+                        || (visited.insert(inner.clone())
+                            && self.contains_type(inner, target, visited))
+                }
+                _ => false,
+            }
+        })
+    }
+
+    /// Looks up a variant of the enum named in `Shape::Circle`.
+    fn variant(&self, name: &Name, variant: &Name) -> LowerResult<(Type, usize, Vec<Type>)> {
+        let ty = self.resolve_named_type(name)?;
+        let found = match &ty {
+            Type::Enum(def) => self.enums[def]
+                .variant(&variant.name)
+                .map(|(index, variant)| (index, variant.fields.clone())),
+            _ => None,
+        };
+
+        match found {
+            Some((index, fields)) => Ok((ty, index, fields)),
+            None => Err(CompileError::UnknownVariant {
+                ty,
+                name: variant.name.clone(),
+                span: variant.span,
+            }),
+        }
+    }
+
+    fn binding(&self, name: &Name) -> BindingId {
+        self.symbols
+            .binding(name)
+            .expect("names are resolved before lowering")
+    }
+
+    fn declare(&mut self, name: &Name, ty: Type) -> BindingId {
+        let id = self.binding(name);
+        self.types.insert(id, ty);
+        id
+    }
+
+    /// Lowers both operands of a binary operator so that a literal on either
+    /// side picks up the type of the other one.
+    fn lower_operands(
+        &mut self,
+        lhs: &gast::Expr,
+        rhs: &gast::Expr,
+        expected: Option<&Type>,
+    ) -> LowerResult<(ast::Expr, ast::Expr)> {
+        if is_literal(lhs) && !is_literal(rhs) {
+            let rhs = self.lower_expr(rhs, expected)?;
+            let lhs = self.lower_expr(lhs, Some(&rhs.ty))?;
+            Ok((lhs, rhs))
+        } else {
+            let lhs = self.lower_expr(lhs, expected)?;
+            let rhs = self.lower_expr(rhs, Some(&lhs.ty))?;
+            Ok((lhs, rhs))
+        }
+    }
+
+    fn lower_expr(&mut self, expr: &gast::Expr, expected: Option<&Type>) -> LowerResult<ast::Expr> {
+        let (kind, ty) = match &expr.kind {
+            ExprKind::IntLiteral(value, suffix) => {
+                return lower_int_literal(*value, *suffix, false, expected, expr.span)
+            }
+            ExprKind::FloatLiteral(float) => {
+                let ty = match expected {
+                    Some(ty @ Type::Float(_)) => ty.clone(),
+                    _ => Type::F64,
+                };
+                (ast::ExprKind::FloatLiteral(*float), ty)
+            }
+            ExprKind::BoolLiteral(boolean) => (ast::ExprKind::BoolLiteral(*boolean), Type::Bool),
+            ExprKind::CharLiteral(char) => (ast::ExprKind::CharLiteral(*char), Type::Char),
+            ExprKind::StringLiteral(string) => {
+                (ast::ExprKind::StringLiteral(string.clone()), Type::Str)
+            }
+            ExprKind::Null => match expected {
+                Some(ty @ Type::Pointer(_)) => (ast::ExprKind::Null, ty.clone()),
+                _ => return Err(CompileError::UntypedNull { span: expr.span }),
+            },
+            ExprKind::Name(name) => {
+                let id = self.binding(name);
+
+                // constants are inlined, with the span of the use
+                if self.consts.contains_key(&id) {
+                    let value = self.lower_const(id)?;
+                    return Ok(ast::Expr {
+                        span: expr.span,
+                        ..value
+                    });
+                }
+
+                (ast::ExprKind::Variable(id), self.types[&id].clone())
+            }
+            ExprKind::Unary(op, operand) => {
+                // negated here, so the range check accepts `-128i8`
+                if let (UnaryOperator::Neg, ExprKind::IntLiteral(value, suffix)) =
+                    (op, &operand.kind)
+                {
+                    return lower_int_literal(*value, *suffix, true, expected, expr.span);
+                }
+
+                let operand = match op {
+                    UnaryOperator::Neg => self.lower_expr(operand, expected)?,
+                    UnaryOperator::Not => self.lower_expr(operand, Some(&Type::Bool))?,
+                };
+
+                let valid = match op {
+                    UnaryOperator::Neg => {
+                        matches!(operand.ty, Type::Int(int) if int.is_signed())
+                            || operand.ty.is_float()
+                    }
+                    UnaryOperator::Not => operand.ty == Type::Bool,
+                };
+                if !valid {
+                    return Err(invalid_operand(op.symbol(), &operand, expr.span));
+                }
+
+                let ty = operand.ty.clone();
+                (ast::ExprKind::Unary(op.clone(), Box::new(operand)), ty)
+            }
+            ExprKind::AddressOf(operand) => {
+                let operand = match expected {
+                    Some(Type::Pointer(pointee)) => self.lower_expr(operand, Some(pointee))?,
+                    _ => self.lower_expr(operand, None)?,
+                };
+
+                if !operand.kind.is_place() {
+                    return Err(CompileError::NotAddressable { span: operand.span });
+                }
+
+                let ty = Type::Pointer(Box::new(operand.ty.clone()));
+                (ast::ExprKind::AddressOf(Box::new(operand)), ty)
+            }
+            ExprKind::Deref(operand) => {
+                let operand = self.lower_expr(operand, None)?;
+
+                let ty = match &operand.ty {
+                    Type::Pointer(pointee) => (**pointee).clone(),
+                    _ => return Err(invalid_operand("*", &operand, expr.span)),
+                };
+                (ast::ExprKind::Deref(Box::new(operand)), ty)
+            }
+            ExprKind::Binary(op, lhs, rhs) => {
+                let expected = if op.is_comparison() { None } else { expected };
+                let (lhs, rhs) = self.lower_operands(lhs, rhs, expected)?;
+
+                let valid = match op {
+                    Operator::Eq | Operator::Ne => {
+                        lhs.ty.is_numeric()
+                            || matches!(lhs.ty, Type::Bool | Type::Char | Type::Pointer(_))
+                    }
+                    _ if op.is_comparison() => lhs.ty.is_numeric() || lhs.ty == Type::Char,
+                    _ => lhs.ty.is_numeric(),
+                };
+                if !valid {
+                    return Err(invalid_operand(op.symbol(), &lhs, expr.span));
+                }
+                expect_type(&rhs, &lhs.ty)?;
+
+                let ty = if op.is_comparison() {
+                    Type::Bool
+                } else {
+                    lhs.ty.clone()
+                };
+                (
+                    ast::ExprKind::Binary(op.clone(), Box::new(lhs), Box::new(rhs)),
+                    ty,
+                )
+            }
+            ExprKind::Logical(op, lhs, rhs) => {
+                let lhs = self.lower_typed_expr(lhs, &Type::Bool)?;
+                let rhs = self.lower_typed_expr(rhs, &Type::Bool)?;
+                (
+                    ast::ExprKind::Logical(op.clone(), Box::new(lhs), Box::new(rhs)),
+                    Type::Bool,
+                )
+            }
+            ExprKind::Cast(operand, ty) => {
+                let operand = self.lower_expr(operand, None)?;
+                let ty = self.resolve_type(ty)?;
+
+                if !is_valid_cast(&operand.ty, &ty) {
+                    return Err(CompileError::InvalidCast {
+                        from: operand.ty,
+                        to: ty,
+                        span: expr.span,
+                    });
+                }
+
+                (ast::ExprKind::Cast(Box::new(operand)), ty)
+            }
+            ExprKind::Call(name, args) if !self.functions.contains_key(&name.name) => {
+                let builtin =
+                    Builtin::from_name(&name.name).expect("calls are resolved before lowering");
+                return self.lower_builtin(builtin, name, args, expected, expr.span);
+            }
+            ExprKind::Call(name, args) => {
+                let signature = &self.functions[&name.name];
+
+                let arity_matches = if signature.variadic {
+                    args.len() >= signature.params.len()
+                } else {
+                    args.len() == signature.params.len()
+                };
+                if !arity_matches {
+                    return Err(CompileError::ArityMismatch {
+                        name: name.name.clone(),
+                        expected: signature.params.len(),
+                        found: args.len(),
+                        span: expr.span,
+                    });
+                }
+
+                let params = signature.params.clone();
+                let ret = signature.ret.clone();
+
+                let mut lowered = Vec::with_capacity(args.len());
+                for (i, arg) in args.iter().enumerate() {
+                    lowered.push(match params.get(i) {
+                        Some(param) => self.lower_typed_expr(arg, param)?,
+                        None => promote_variadic(self.lower_expr(arg, None)?, name)?,
+                    });
+                }
+                let args = lowered;
+
+                (ast::ExprKind::Call(name.name.clone(), args), ret)
+            }
+            ExprKind::StructLiteral(name, fields) => {
+                let ty = self.resolve_named_type(name)?;
+                let def = match &ty {
+                    Type::Struct(name) => Some(self.structs[name].clone()),
+                    _ => None,
+                };
+
+                let mut values: Vec<(usize, ast::Expr)> = Vec::with_capacity(fields.len());
+                for (field, value) in fields {
+                    let (index, field_ty) = def
+                        .as_ref()
+                        .and_then(|def| def.field(&field.name))
+                        .map(|(index, field)| (index, field.ty.clone()))
+                        .ok_or_else(|| CompileError::UnknownField {
+                            ty: ty.clone(),
+                            name: field.name.clone(),
+                            span: field.span,
+                        })?;
+
+                    if values.iter().any(|(other, _)| *other == index) {
+                        return Err(CompileError::DuplicateField {
+                            name: field.name.clone(),
+                            span: field.span,
+                        });
+                    }
+
+                    values.push((index, self.lower_typed_expr(value, &field_ty)?));
+                }
+
+                // every field is known, so the literal names a struct
+                let def = def.expect("fields of a non-struct are rejected above");
+                if let Some(missing) = def
+                    .fields
+                    .iter()
+                    .enumerate()
+                    .find(|(index, _)| values.iter().all(|(other, _)| other != index))
+                    .map(|(_, field)| field)
+                {
+                    return Err(CompileError::MissingField {
+                        ty,
+                        name: missing.name.clone(),
+                        span: name.span,
+                    });
+                }
+
+                (ast::ExprKind::StructLiteral(values), ty)
+            }
+            ExprKind::Field(base, name) => {
+                let mut base = self.lower_expr(base, None)?;
+
+                // fields are read through a pointer to a struct, so `p.x`
+                // is `(*p).x`
+                if let Type::Pointer(pointee) = &base.ty {
+                    if let Type::Struct(_) = **pointee {
+                        let (ty, span) = ((**pointee).clone(), base.span);
+                        base = ast::Expr::new(ast::ExprKind::Deref(Box::new(base)), ty, span);
+                    }
+                }
+
+                let field = match &base.ty {
+                    Type::Struct(def) => self.structs[def]
+                        .field(&name.name)
+                        .map(|(index, field)| (index, field.ty.clone())),
+                    _ => None,
+                };
+
+                let (index, ty) = field.ok_or_else(|| CompileError::UnknownField {
+                    ty: base.ty.clone(),
+                    name: name.name.clone(),
+                    span: name.span,
+                })?;
+                (ast::ExprKind::Field(Box::new(base), index), ty)
+            }
+            ExprKind::Variant(name, variant, values) => {
+                let (ty, index, fields) = self.variant(name, variant)?;
+
+                if values.len() != fields.len() {
+                    return Err(CompileError::ArityMismatch {
+                        name: format!("{}::{}", name.name, variant.name),
+                        expected: fields.len(),
+                        found: values.len(),
+                        span: expr.span,
+                    });
+                }
+
+                let values = values
+                    .iter()
+                    .zip(&fields)
+                    .map(|(value, field)| self.lower_typed_expr(value, field))
+                    .collect::<LowerResult<_>>()?;
+                (ast::ExprKind::Variant(index, values), ty)
+            }
+            ExprKind::ArrayLiteral(values) => {
+                // the first element decides the type, unless the context
+                // already expects one
+                let mut element = match expected {
+                    Some(Type::Array(element, _) | Type::Slice(element)) => {
+                        Some((**element).clone())
+                    }
+                    _ => None,
+                };
+
+                let mut lowered = Vec::with_capacity(values.len());
+                for value in values {
+                    let value = match &element {
+                        Some(element) => self.lower_typed_expr(value, element)?,
+                        None => self.lower_expr(value, None)?,
+                    };
+                    element.get_or_insert_with(|| value.ty.clone());
+                    lowered.push(value);
+                }
+
+                let element = element.ok_or(CompileError::EmptyArrayLiteral { span: expr.span })?;
+                let len = lowered.len() as u64;
+                (
+                    ast::ExprKind::ArrayLiteral(lowered),
+                    Type::Array(Box::new(element), len),
+                )
+            }
+            ExprKind::Index(base, index) => {
+                let base = self.lower_expr(base, None)?;
+                let element = match &base.ty {
+                    Type::Array(element, _) | Type::Slice(element) => (**element).clone(),
+                    _ => {
+                        return Err(CompileError::NotIndexable {
+                            ty: base.ty.clone(),
+                            span: base.span,
+                        })
+                    }
+                };
+
+                // any integer can index, it is compared as an unsigned value,
+                // so negative indices are out of bounds as well
+                let index = self.lower_expr(index, Some(&Type::I64))?;
+                if !matches!(index.ty, Type::Int(_)) {
+                    return Err(CompileError::TypeMismatch {
+                        expected: Type::I64,
+                        found: index.ty,
+                        span: index.span,
+                    });
+                }
+
+                (
+                    ast::ExprKind::Index(Box::new(base), Box::new(cast_to(index, Type::I64))),
+                    element,
+                )
+            }
+            ExprKind::Match(scrutinee, arms) => {
+                let scrutinee = self.lower_expr(scrutinee, None)?;
+
+                // the first arm producing a value decides the type, unless
+                // the context already expects one
+                let mut ty = None;
+                let mut lowered = Vec::with_capacity(arms.len());
+
+                for arm in arms {
+                    let pattern = self.lower_pattern(&arm.pattern, &scrutinee.ty)?;
+                    let body = self.lower_block(&arm.body)?;
+
+                    let value = match &arm.value {
+                        Some(value) => {
+                            let value = match &ty {
+                                Some(ty) => self.lower_typed_expr(value, ty)?,
+                                None => self.lower_expr(value, expected)?,
+                            };
+                            ty.get_or_insert_with(|| value.ty.clone());
+                            Some(value)
+                        }
+                        // a block that never finishes fits any type
+                        None if always_returns(&body) => None,
+                        None => {
+                            match &ty {
+                                Some(ty) if *ty != Type::Unit => {
+                                    return Err(CompileError::TypeMismatch {
+                                        expected: ty.clone(),
+                                        found: Type::Unit,
+                                        span: arm.span,
+                                    })
+                                }
+                                _ => ty = Some(Type::Unit),
+                            }
+                            None
+                        }
+                    };
+
+                    lowered.push(ast::Arm {
+                        pattern,
+                        body,
+                        value,
+                    });
+                }
+
+                check_match(&self.enums, &scrutinee.ty, &lowered, expr.span)?;
+
+                (
+                    ast::ExprKind::Match(Box::new(scrutinee), lowered),
+                    ty.unwrap_or(Type::Unit),
+                )
+            }
+        };
+
+        Ok(ast::Expr::new(kind, ty, expr.span))
+    }
+
+    /// Lowers a pattern matching values of the type `ty`, declaring the
+    /// variables it binds.
+    fn lower_pattern(&mut self, pattern: &gast::Pattern, ty: &Type) -> LowerResult<ast::Pattern> {
+        let kind = match &pattern.kind {
+            PatternKind::Wildcard => ast::PatternKind::Wildcard,
+            PatternKind::Binding(name) => ast::PatternKind::Binding(self.declare(name, ty.clone())),
+            PatternKind::Literal(literal) => {
+                // integer literals are never typed as floats, so only
+                // integers, booleans and characters can be matched by value
+                ast::PatternKind::Literal(self.lower_typed_expr(literal, ty)?)
+            }
+            PatternKind::Variant(name, variant, fields) => {
+                let (variant_ty, index, field_types) = self.variant(name, variant)?;
+
+                if variant_ty != *ty {
+                    return Err(CompileError::TypeMismatch {
+                        expected: ty.clone(),
+                        found: variant_ty,
+                        span: pattern.span,
+                    });
+                }
+
+                if fields.len() != field_types.len() {
+                    return Err(CompileError::ArityMismatch {
+                        name: format!("{}::{}", name.name, variant.name),
+                        expected: field_types.len(),
+                        found: fields.len(),
+                        span: pattern.span,
+                    });
+                }
+
+                let fields = fields
+                    .iter()
+                    .zip(&field_types)
+                    .map(|(field, ty)| self.lower_pattern(field, ty))
+                    .collect::<LowerResult<_>>()?;
+                ast::PatternKind::Variant(index, fields)
+            }
+        };
+
+        Ok(ast::Pattern {
+            kind,
+            ty: ty.clone(),
+            span: pattern.span,
+        })
+    }
+
+    /// Lowers a call of a built-in to a call of the runtime function
+    /// implementing it. `print` and `println` accept any primitive value and
+    /// widen it to the 64 bit type the runtime prints. `len`, `alloc` and
+    /// `free` need no runtime support and are lowered to their own
+    /// expressions.
+    fn lower_builtin(
+        &mut self,
+        builtin: Builtin,
+        name: &Name,
+        args: &[gast::Expr],
+        expected: Option<&Type>,
+        span: Span,
+    ) -> LowerResult<ast::Expr> {
+        if args.len() != builtin.arity() {
+            return Err(CompileError::ArityMismatch {
+                name: name.name.clone(),
+                expected: builtin.arity(),
+                found: args.len(),
+                span,
+            });
+        }
+
+        let (function, args, ty) = match builtin {
+            Builtin::Print | Builtin::Println => {
+                let arg = self.lower_expr(&args[0], None)?;
+
+                let (suffix, widened) = match arg.ty {
+                    Type::Int(int) if int.is_signed() => ("i64", Type::I64),
+                    Type::Int(_) => ("u64", Type::Int(IntType::U64)),
+                    Type::Float(_) => ("f64", Type::F64),
+                    Type::Bool => ("bool", Type::I64),
+                    Type::Char => ("char", Type::Char),
+                    Type::Str => ("str", Type::Str),
+                    Type::Unit
+                    | Type::Struct(_)
+                    | Type::Enum(_)
+                    | Type::Array(..)
+                    | Type::Slice(_)
+                    | Type::Pointer(_) => return Err(invalid_operand(&name.name, &arg, span)),
+                };
+
+                let arg = cast_to(arg, widened);
+
+                let function = match builtin {
+                    Builtin::Print => format!("__ac_print_{}", suffix),
+                    _ => format!("__ac_println_{}", suffix),
+                };
+                (function, vec![arg], Type::Unit)
+            }
+            Builtin::Len => {
+                let arg = self.lower_expr(&args[0], None)?;
+
+                if !matches!(arg.ty, Type::Array(..) | Type::Slice(_) | Type::Str) {
+                    return Err(invalid_operand(&name.name, &arg, span));
+                }
+
+                return Ok(ast::Expr::new(
+                    ast::ExprKind::Len(Box::new(arg)),
+                    Type::I64,
+                    span,
+                ));
+            }
+            Builtin::Alloc => {
+                let value = match expected {
+                    Some(Type::Pointer(pointee)) => self.lower_typed_expr(&args[0], pointee)?,
+                    _ => self.lower_expr(&args[0], None)?,
+                };
+
+                let ty = Type::Pointer(Box::new(value.ty.clone()));
+                return Ok(ast::Expr::new(
+                    ast::ExprKind::Alloc(Box::new(value)),
+                    ty,
+                    span,
+                ));
+            }
+            Builtin::Free => {
+                let arg = self.lower_expr(&args[0], None)?;
+
+                if !matches!(arg.ty, Type::Pointer(_)) {
+                    return Err(invalid_operand(&name.name, &arg, span));
+                }
+
+                return Ok(ast::Expr::new(
+                    ast::ExprKind::Free(Box::new(arg)),
+                    Type::Unit,
+                    span,
+                ));
+            }
+            Builtin::ReadInt => ("__ac_read_int".to_string(), Vec::new(), Type::I64),
+            Builtin::ReadLine => ("__ac_read_line".to_string(), Vec::new(), Type::Str),
+        };
+
+        Ok(ast::Expr::new(
+            ast::ExprKind::Call(function, args),
+            ty,
+            span,
+        ))
+    }
+
+    /// Lowers an expression that must have the given type. An array is
+    /// turned into a slice of its elements where a slice is expected.
+    fn lower_typed_expr(&mut self, expr: &gast::Expr, ty: &Type) -> LowerResult<ast::Expr> {
+        let expr = self.lower_expr(expr, Some(ty))?;
+
+        let expr = match (&expr.ty, ty) {
+            (Type::Array(element, _), Type::Slice(expected)) if element == expected => {
+                let span = expr.span;
+                ast::Expr::new(ast::ExprKind::AsSlice(Box::new(expr)), ty.clone(), span)
+            }
+            _ => expr,
+        };
+
+        expect_type(&expr, ty)?;
+        Ok(expr)
+    }
+
+    fn lower_block(&mut self, block: &[gast::Stmt]) -> LowerResult<Vec<ast::Stmt>> {
+        block.iter().map(|stmt| self.lower_stmt(stmt)).collect()
+    }
+
+    fn lower_stmt(&mut self, stmt: &gast::Stmt) -> LowerResult<ast::Stmt> {
+        let kind = match &stmt.kind {
+            StmtKind::Expr(expr) => ast::StmtKind::Expr(self.lower_expr(expr, None)?),
+            StmtKind::Let(name, ty, expr) => {
+                let value = match ty {
+                    Some(ty) => self.lower_typed_expr(expr, &self.resolve_type(ty)?)?,
+                    None => self.lower_expr(expr, None)?,
+                };
+
+                let id = self.declare(name, value.ty.clone());
+                ast::StmtKind::Let(id, value)
+            }
+            StmtKind::Assign(place, expr) => {
+                let place = self.lower_expr(place, None)?;
+                if !place.kind.is_place() {
+                    return Err(CompileError::InvalidAssignment { span: place.span });
+                }
+
+                let value = self.lower_typed_expr(expr, &place.ty)?;
+                ast::StmtKind::Assign(place, value)
+            }
+            StmtKind::Return(Some(expr)) => {
+                let ret = self.ret.clone();
+                ast::StmtKind::Return(Some(self.lower_typed_expr(expr, &ret)?))
+            }
+            StmtKind::Return(None) if self.ret != Type::Unit => {
+                return Err(CompileError::TypeMismatch {
+                    expected: self.ret.clone(),
+                    found: Type::Unit,
+                    span: stmt.span,
+                })
+            }
+            StmtKind::Return(None) => ast::StmtKind::Return(None),
+            StmtKind::If(cond, then_b, else_b) => ast::StmtKind::If(
+                self.lower_typed_expr(cond, &Type::Bool)?,
+                self.lower_block(then_b)?,
+                else_b
+                    .as_ref()
+                    .map(|else_b| self.lower_block(else_b))
+                    .transpose()?,
+            ),
+            StmtKind::For(init, cond, step, body) => {
+                // `for init; cond; step { body }` becomes `{ init; loop }`, so
+                // the binding of `init` is scoped to the loop
+                let init = self.lower_stmt(init)?;
+                let cond = self.lower_typed_expr(cond, &Type::Bool)?;
+                let body = self.lower_block(body)?;
+                let step = self.lower_stmt(step)?;
+
+                ast::StmtKind::Block(vec![
+                    init,
+                    ast::Stmt::new(ast::StmtKind::Loop(Some(cond), body, vec![step]), stmt.span),
+                ])
+            }
+            StmtKind::While(cond, body) => ast::StmtKind::Loop(
+                Some(self.lower_typed_expr(cond, &Type::Bool)?),
+                self.lower_block(body)?,
+                Vec::new(),
+            ),
+            StmtKind::Loop(body) => ast::StmtKind::Loop(None, self.lower_block(body)?, Vec::new()),
+            StmtKind::Break => ast::StmtKind::Break,
+            StmtKind::Continue => ast::StmtKind::Continue,
+        };
+
+        Ok(ast::Stmt::new(kind, stmt.span))
+    }
+
+    fn lower_function(&mut self, function: &gast::Function) -> LowerResult<ast::Function> {
+        let signature = &self.functions[&function.name.name];
+        let param_types = signature.params.clone();
+        self.ret = signature.ret.clone();
+
+        let params = function
+            .args
+            .iter()
+            .zip(param_types)
+            .map(|(arg, ty)| self.declare(&arg.name, ty))
+            .collect();
+
+        let body = self.lower_block(&function.body)?;
+
+        // only `unit` functions may fall off the end of their body
+        if self.ret != Type::Unit && !always_returns(&body) {
+            let end = function.span.end;
+            return Err(CompileError::MissingReturn {
+                name: function.name.name.clone(),
+                ty: self.ret.clone(),
+                span: Span::new(end - 1, end),
+            });
+        }
+
+        Ok(ast::Function {
+            name: function.name.name.clone(),
+            params,
+            ret: self.ret.clone(),
+            body,
+            span: function.span,
+        })
+    }
+
+    /// Evaluates the initializer of a constant, or returns its value if it
+    /// already has been.
+    fn lower_const(&mut self, id: BindingId) -> LowerResult<ast::Expr> {
+        if let Some(value) = self.const_values.get(&id) {
+            return Ok(value.clone());
+        }
+
+        let def = self.consts[&id];
+        if self.evaluating.contains(&id) {
+            return Err(CompileError::RecursiveConstant {
+                name: def.name.name.clone(),
+                span: def.name.span,
+            });
+        }
+
+        self.evaluating.push(id);
+        let value = match &def.ty {
+            Some(ty) => self.lower_typed_expr(&def.value, &self.resolve_type(ty)?)?,
+            None => self.lower_expr(&def.value, None)?,
+        };
+        let value = evaluate(value)?;
+        self.evaluating.pop();
+
+        self.types.insert(id, value.ty.clone());
+        self.const_values.insert(id, value.clone());
+        Ok(value)
+    }
+
+    fn lower_static(&mut self, def: &gast::Static) -> LowerResult<ast::Static> {
+        let id = self.binding(&def.name);
+        let ty = self.types[&id].clone();
+
+        Ok(ast::Static {
+            id,
+            mutable: def.mutable,
+            value: evaluate(self.lower_typed_expr(&def.value, &ty)?)?,
+            span: def.span,
+        })
+    }
+
+    fn lower_signature(&self, function: &gast::Function) -> LowerResult<Signature> {
+        let params = function
+            .args
+            .iter()
+            .map(|arg| self.resolve_type(&arg.ty))
+            .collect::<LowerResult<_>>()?;
+
+        let ret = match &function.ret {
+            Some(ret) => self.resolve_type(ret)?,
+            None => Type::Unit,
+        };
+
+        Ok(Signature {
+            params,
+            variadic: false,
+            ret,
+        })
+    }
+
+    fn lower_extern(&self, function: &gast::ExternFunction) -> LowerResult<ast::ExternFunction> {
+        let params = function
+            .args
+            .iter()
+            .map(|arg| self.resolve_type(&arg.ty))
+            .collect::<LowerResult<Vec<_>>>()?;
+
+        // structs, enums, arrays and slices have no C layout yet
+        if let Some((arg, ty)) = function
+            .args
+            .iter()
+            .zip(&params)
+            .find(|(_, ty)| ty.is_aggregate() || matches!(ty, Type::Slice(_)))
+        {
+            return Err(CompileError::InvalidExternParameter {
+                ty: ty.clone(),
+                span: arg.ty.span,
+            });
+        }
+
+        // extern functions without a declared return type are `void` in C
+        let ret = match &function.ret {
+            Some(ret) => self.resolve_type(ret)?,
+            None => Type::Unit,
+        };
+
+        // C cannot hand back the length of a string or a slice, and structs,
+        // enums and arrays have no C layout yet
+        if matches!(ret, Type::Str | Type::Slice(_)) || ret.is_aggregate() {
+            return Err(CompileError::InvalidExternReturn {
+                ty: ret,
+                span: function.ret.as_ref().map_or(function.span, |ret| ret.span),
+            });
+        }
+
+        Ok(ast::ExternFunction {
+            name: function.name.name.clone(),
+            params,
+            variadic: function.variadic,
+            ret,
+            span: function.span,
+        })
+    }
+}
+
+pub fn lower_program<'a>(
+    program: &'a gast::Program,
+    symbols: &'a SymbolTable,
+) -> LowerResult<ast::Program> {
+    let mut lowering = Lowering {
+        symbols,
+        structs: HashMap::new(),
+        enums: HashMap::new(),
+        functions: HashMap::new(),
+        types: HashMap::new(),
+        ret: Type::Unit,
+        consts: HashMap::new(),
+        const_values: HashMap::new(),
+        evaluating: Vec::new(),
+    };
+
+    // declare every struct and enum before resolving the types of the
+    // fields, so they can refer to types declared later in the file
+    for def in &program.structs {
+        let def = ast::Struct {
+            name: def.name.name.clone(),
+            fields: Vec::new(),
+            span: def.span,
+        };
+        lowering.structs.insert(def.name.clone(), def);
+    }
+
+    for def in &program.enums {
+        let def = ast::Enum {
+            name: def.name.name.clone(),
+            variants: Vec::new(),
+            span: def.span,
+        };
+        lowering.enums.insert(def.name.clone(), def);
+    }
+
+    for def in &program.structs {
+        let fields = def
+            .fields
+            .iter()
+            .map(|field| {
+                Ok(ast::Field {
+                    name: field.name.name.clone(),
+                    ty: lowering.resolve_type(&field.ty)?,
+                })
+            })
+            .collect::<LowerResult<_>>()?;
+
+        lowering
+            .structs
+            .get_mut(&def.name.name)
+            .expect("struct is declared above")
+            .fields = fields;
+    }
+
+    for def in &program.enums {
+        let variants = def
+            .variants
+            .iter()
+            .map(|variant| {
+                Ok(ast::Variant {
+                    name: variant.name.name.clone(),
+                    fields: variant
+                        .fields
+                        .iter()
+                        .map(|field| lowering.resolve_type(field))
+                        .collect::<LowerResult<_>>()?,
+                })
+            })
+            .collect::<LowerResult<_>>()?;
+
+        lowering
+            .enums
+            .get_mut(&def.name.name)
+            .expect("enum is declared above")
+            .variants = variants;
+    }
+
+    let type_names = program
+        .structs
+        .iter()
+        .map(|def| &def.name)
+        .chain(program.enums.iter().map(|def| &def.name));
+
+    for name in type_names {
+        if lowering.contains_type(&name.name, &name.name, &mut HashSet::new()) {
+            return Err(CompileError::RecursiveType {
+                name: name.name.clone(),
+                span: name.span,
+            });
+        }
+    }
+
+    let externs = program
+        .externs
+        .iter()
+        .map(|function| lowering.lower_extern(function))
+        .collect::<LowerResult<Vec<_>>>()?;
+
+    for function in &externs {
+        let signature = Signature {
+            params: function.params.clone(),
+            variadic: function.variadic,
+            ret: function.ret.clone(),
+        };
+        lowering.functions.insert(function.name.clone(), signature);
+    }
+
+    // collect every signature first, so that bodies can call functions
+    // defined later in the file
+    for function in &program.functions {
+        let signature = lowering.lower_signature(function)?;
+        lowering
+            .functions
+            .insert(function.name.name.clone(), signature);
+    }
+
+    // the types of statics are known before any initializer is lowered, a
+    // constant referring to one is then reported as not constant
+    for def in &program.statics {
+        let ty = lowering.resolve_type(&def.ty)?;
+        lowering.declare(&def.name, ty);
+    }
+
+    for def in &program.consts {
+        lowering.consts.insert(lowering.binding(&def.name), def);
+    }
+
+    // every constant is evaluated, also those that are never used
+    for def in &program.consts {
+        lowering.lower_const(lowering.binding(&def.name))?;
+    }
+
+    let statics = program
+        .statics
+        .iter()
+        .map(|def| lowering.lower_static(def))
+        .collect::<LowerResult<_>>()?;
+
+    let functions = program
+        .functions
+        .iter()
+        .map(|function| lowering.lower_function(function))
+        .collect::<LowerResult<_>>()?;
+
+    let bindings = symbols
+        .symbols
+        .iter()
+        .enumerate()
+        .map(|(id, symbol)| Binding {
+            name: symbol.name.clone(),
+            ty: lowering.types[&BindingId(id)].clone(),
+            span: symbol.span,
+        })
+        .collect();
+
+    let structs = program
+        .structs
+        .iter()
+        .map(|def| lowering.structs[&def.name.name].clone())
+        .collect();
+
+    let enums = program
+        .enums
+        .iter()
+        .map(|def| lowering.enums[&def.name.name].clone())
+        .collect();
+
+    Ok(ast::Program {
+        functions,
+        externs,
+        structs,
+        enums,
+        statics,
+        bindings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::parse_source, resolver::resolve_program};
+
+    /// Typed ast of a program free of syntax and name errors, or the message
+    /// of the type error.
+    fn lower(code: &str) -> Result<ast::Program, String> {
+        let (program, errors) = parse_source(code);
+        assert!(errors.is_empty(), "{:?}", errors);
+        let resolution = resolve_program(&program);
+        assert!(!resolution.has_errors(), "{:?}", resolution.diagnostics);
+
+        lower_program(&program, &resolution.symbols).map_err(|error| error.to_string())
+    }
+
+    /// Bits of a typed integer literal, or the message of its error.
+    fn int_literal(value: u64, suffix: IntType, negative: bool) -> Result<u64, String> {
+        match lower_int_literal(value, Some(suffix), negative, None, Span::default()) {
+            Ok(ast::Expr {
+                kind: ast::ExprKind::IntLiteral(value),
+                ..
+            }) => Ok(value),
+            Ok(expr) => panic!("{:?} is not an integer literal", expr),
+            Err(error) => Err(error.to_string()),
+        }
+    }
+
+    #[test]
+    fn integer_literals_fit_their_type() {
+        assert_eq!(int_literal(255, IntType::U8, false), Ok(255));
+        assert_eq!(int_literal(128, IntType::I8, true), Ok(-128i64 as u64));
+        assert_eq!(
+            int_literal(256, IntType::U8, false),
+            Err("literal out of range for `u8`".to_string())
+        );
+        assert_eq!(
+            int_literal(128, IntType::I8, false),
+            Err("literal out of range for `i8`".to_string())
+        );
+    }
+
+    #[test]
+    fn field_access_lowers_to_the_index_of_the_field() {
+        let program = lower(
+            "struct Point { x: i64, visible: bool }
+
+            fun visible(point: Point) -> bool {
+                return point.visible;
+            }",
+        )
+        .unwrap();
+
+        match &program.functions[0].body[0].kind {
+            ast::StmtKind::Return(Some(expr)) => {
+                assert_eq!(expr.ty, Type::Bool);
+                assert!(matches!(expr.kind, ast::ExprKind::Field(_, 1)));
+            }
+            kind => panic!("{:?} is not a return", kind),
+        }
+
+        let unknown = lower(
+            "struct Point { x: i64 }
+
+            fun y(point: Point) -> i64 {
+                return point.y;
+            }",
+        );
+        assert_eq!(
+            unknown.unwrap_err(),
+            "type `Point` has no field `y`".to_string()
+        );
+    }
+
+    #[test]
+    fn stores_through_a_dereferenced_pointer() {
+        let program = lower(
+            "fun swap(a: *i64, b: *i64) {
+                let t = *a;
+                *a = *b;
+                *b = t;
+            }",
+        )
+        .unwrap();
+
+        match &program.functions[0].body[1].kind {
+            ast::StmtKind::Assign(place, value) => {
+                assert!(matches!(place.kind, ast::ExprKind::Deref(_)));
+                assert!(matches!(value.kind, ast::ExprKind::Deref(_)));
+                assert_eq!(place.ty, Type::I64);
+            }
+            kind => panic!("{:?} is not an assignment", kind),
+        }
+
+        assert_eq!(
+            lower("fun f(a: i64) { *a = 1; }").unwrap_err(),
+            "cannot apply `*` to a value of type `i64`".to_string()
+        );
+    }
+}
@@ -1,26 +1,56 @@
-use std::{fs::read_to_string, path::Path, process::Command};
+use std::{
+    fs::{read_to_string, write},
+    path::Path,
+    process::Command,
+};
 
+use anyhow::{anyhow, bail};
 use inkwell::{
     context::Context,
     passes::PassManager,
     targets::{CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine},
     OptimizationLevel,
 };
-use pest::Parser;
 
-use crate::{code_generator::Compiler, parser::parse_program};
+use crate::{
+    builtins::RUNTIME_SOURCE, code_generator::Compiler, diagnostics::SourceFile,
+    lowering::lower_program, parser::parse_source, resolver::resolve_program,
+};
 
+pub mod builtins;
 pub mod code_generator;
+pub mod const_eval;
+pub mod diagnostics;
+pub mod error;
+pub mod exhaustiveness;
 pub mod internal_representations;
+pub mod lowering;
 pub mod parser;
+pub mod resolver;
 pub mod state_machine;
 
-fn generate_object_code(code: String, name: &str) {
-    let ast = parser::Parser::parse(parser::Rule::program, &code)
-        .unwrap()
-        .next()
-        .unwrap();
-    let program = parse_program(ast).unwrap();
+fn generate_object_code(
+    source: &SourceFile,
+    name: &str,
+    bounds_checks: bool,
+) -> anyhow::Result<()> {
+    let (program, syntax_errors) = parse_source(&source.code);
+    for error in &syntax_errors {
+        eprintln!("{}", error.render(source));
+    }
+
+    // the partial program of a file with syntax errors is still resolved, so
+    // its name errors are reported in the same run
+    let resolution = resolve_program(&program);
+    for diagnostic in &resolution.diagnostics {
+        eprintln!("{}", diagnostic.render(source));
+    }
+    if !syntax_errors.is_empty() || resolution.has_errors() {
+        bail!("could not compile `{}` due to previous errors", source.name);
+    }
+
+    let program = lower_program(&program, &resolution.symbols)
+        .map_err(|error| anyhow!(error.render(source)))?;
 
     Target::initialize_x86(&InitializationConfig::default());
 
@@ -59,8 +89,16 @@ fn generate_object_code(code: String, name: &str) {
 
     fpm.initialize();
 
-    let _function: inkwell::values::FunctionValue =
-        Compiler::compile(&context, &builder, &fpm, &module, &program).unwrap();
+    Compiler::compile(
+        &context,
+        &builder,
+        &fpm,
+        &module,
+        &program,
+        source,
+        bounds_checks,
+    )
+    .map_err(|error| anyhow!(error.render(source)))?;
 
     target_machine
         .write_to_file(&module, FileType::Object, Path::new(&format!("{}.o", name)))
@@ -73,32 +111,81 @@ fn generate_object_code(code: String, name: &str) {
             Path::new(&format!("{}.as", name)),
         )
         .unwrap();
+
+    Ok(())
 }
 
-fn generate_executable(name: &str) {
-    let mut command = Command::new("clang++-12");
+/// Links the object file with the runtime library, which is written next to
+/// it and compiled in the same clang invocation.
+fn generate_executable(name: &str) -> anyhow::Result<()> {
+    let runtime = format!("{}.runtime.c", name);
+    write(&runtime, RUNTIME_SOURCE)?;
+
+    let mut command = Command::new("clang-12");
     command.arg(format!("{}.o", name));
+    command.arg(&runtime);
     command.arg("-o");
     command.arg(name);
-    command.output();
-}
-
-fn main() {
-    // get file name
-    let args: Vec<String> = std::env::args().collect();
 
-    if args.len() < 2 {
-        println!("Please provide a file name");
-        return;
+    let output = command.output()?;
+    if !output.status.success() {
+        bail!(
+            "linking `{}` failed:\n{}",
+            name,
+            String::from_utf8_lossy(&output.stderr)
+        );
     }
 
-    let file_name = &args[1];
-
-    let code = read_to_string(file_name).unwrap();
+    Ok(())
+}
 
-    let name = file_name.strip_suffix(".ac").unwrap();
+fn main() {
+    let mut file_name = None;
+    let mut bounds_checks = true;
+
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            // indexing out of bounds is undefined behavior without the checks
+            "--no-bounds-checks" => bounds_checks = false,
+            option if option.starts_with("--") => {
+                eprintln!("error: unknown option `{}`", option);
+                std::process::exit(1);
+            }
+            _ if file_name.is_none() => file_name = Some(arg),
+            _ => {
+                eprintln!("error: expected a single file name, found `{}`", arg);
+                std::process::exit(1);
+            }
+        }
+    }
 
-    generate_object_code(code, name);
+    let file_name = match file_name {
+        Some(file_name) => file_name,
+        None => {
+            eprintln!("Please provide a file name");
+            std::process::exit(1);
+        }
+    };
+
+    let code = match read_to_string(&file_name) {
+        Ok(code) => code,
+        Err(error) => {
+            eprintln!("error: could not read `{}`: {}", file_name, error);
+            std::process::exit(1);
+        }
+    };
+
+    let name = file_name.strip_suffix(".ac").unwrap_or(&file_name);
+
+    let source = SourceFile::new(file_name.clone(), code);
+
+    if let Err(error) = generate_object_code(&source, name, bounds_checks) {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    }
 
-    generate_executable(name);
+    if let Err(error) = generate_executable(name) {
+        eprintln!("error: {}", error);
+        std::process::exit(1);
+    }
 }
@@ -1,165 +1,1221 @@
-use pest::iterators::Pair;
+use std::sync::OnceLock;
+
+use pest::{
+    error::ErrorVariant,
+    iterators::{Pair, Pairs},
+    pratt_parser::{Assoc, Op, PrattParser},
+    Parser as _,
+};
 use pest_derive::Parser;
 
-use crate::internal_representations::gast::{Expr, Function, Name, Program, Stmt};
+use crate::{
+    diagnostics::Span,
+    error::CompileError,
+    internal_representations::{
+        ast::Type,
+        gast::{
+            Arm, Const, Enum, Expr, ExprKind, ExternFunction, Field, Function, LogicalOperator,
+            Name, Operator, Param, Pattern, PatternKind, Program, Static, Stmt, StmtKind, Struct,
+            TypeName, TypeNameKind, UnaryOperator, Variant,
+        },
+    },
+};
 
 #[derive(Parser)]
 #[grammar = "parser.pest"]
 pub struct Parser;
 
-fn parse_expr3(code: Pair<Rule>) -> Option<Expr> {
-    let mut iter = code.into_inner();
-    let inner = iter.next()?;
+/// Binary operators ordered from the lowest to the highest precedence. Every
+/// level is a group of operators that bind equally tight.
+const PRECEDENCE_TABLE: &[&[Rule]] = &[
+    &[Rule::or],
+    &[Rule::and],
+    &[Rule::eq, Rule::ne],
+    &[Rule::lt, Rule::le, Rule::gt, Rule::ge],
+    &[Rule::add, Rule::sub],
+    &[Rule::mul, Rule::div],
+];
+
+fn pratt_parser() -> &'static PrattParser<Rule> {
+    static PRATT_PARSER: OnceLock<PrattParser<Rule>> = OnceLock::new();
 
-    match inner.as_rule() {
-        Rule::number => Some(Expr::IntLiteral(inner.as_str().parse().unwrap())),
-        Rule::string => Some(Expr::StringLiteral(inner.as_str().to_string())),
-        Rule::ident => Some(Expr::Name(Name::new(inner.as_str().to_string()))),
-        Rule::expr => parse_expr(inner),
-        _ => unreachable!(),
+    PRATT_PARSER.get_or_init(|| {
+        PRECEDENCE_TABLE
+            .iter()
+            .fold(PrattParser::new(), |parser, level| {
+                let op = level
+                    .iter()
+                    .map(|rule| Op::infix(*rule, Assoc::Left))
+                    .reduce(|ops, op| ops | op)
+                    .expect("empty precedence level");
+                parser.op(op)
+            })
+            // casts bind tighter than any binary operator, prefix operators
+            // tighter than casts, so `-x as u8` is `(-x) as u8`, and field
+            // accesses and indexing tightest, so `-p.x` is `-(p.x)` and
+            // `&a[i]` is `&(a[i])`
+            .op(Op::postfix(Rule::cast))
+            .op(Op::prefix(Rule::neg)
+                | Op::prefix(Rule::not)
+                | Op::prefix(Rule::address_of)
+                | Op::prefix(Rule::deref))
+            .op(Op::postfix(Rule::member) | Op::postfix(Rule::index))
+    })
+}
+
+type ParseResult<T> = Result<T, CompileError>;
+
+/// Errors of the statements and functions the parser skipped.
+type Errors = Vec<CompileError>;
+
+/// Human readable name of a rule the parser expected.
+fn describe_rule(rule: Rule) -> Option<&'static str> {
+    Some(match rule {
+        Rule::semi => "`;`",
+        Rule::ident => "identifier",
+        Rule::type_name | Rule::array_type | Rule::slice_type | Rule::pointer_type => "type",
+        Rule::param => "parameter",
+        Rule::field | Rule::field_init => "field",
+        Rule::variant => "variant",
+        Rule::match_arm => "match arm",
+        Rule::pattern | Rule::wildcard | Rule::variant_pattern | Rule::literal_pattern => "pattern",
+        Rule::variadic => "`...`",
+        Rule::block => "block",
+        Rule::stmt
+        | Rule::let_expr
+        | Rule::assign_expr
+        | Rule::if_expr
+        | Rule::for_expr
+        | Rule::while_expr
+        | Rule::loop_expr
+        | Rule::break_expr
+        | Rule::continue_expr
+        | Rule::return_expr => "statement",
+        Rule::expr
+        | Rule::call_expr
+        | Rule::struct_literal
+        | Rule::array_literal
+        | Rule::variant_expr
+        | Rule::match_expr
+        | Rule::boolean
+        | Rule::null
+        | Rule::float
+        | Rule::number
+        | Rule::string
+        | Rule::character
+        | Rule::neg
+        | Rule::not
+        | Rule::address_of
+        | Rule::deref => "expression",
+        Rule::cast
+        | Rule::member
+        | Rule::index
+        | Rule::or
+        | Rule::and
+        | Rule::eq
+        | Rule::ne
+        | Rule::le
+        | Rule::ge
+        | Rule::lt
+        | Rule::gt
+        | Rule::add
+        | Rule::sub
+        | Rule::mul
+        | Rule::div => "operator",
+        Rule::func | Rule::extern_func | Rule::single_item | Rule::doc_comment => "function",
+        Rule::struct_decl => "struct",
+        Rule::enum_decl => "enum",
+        Rule::const_decl => "constant",
+        Rule::static_decl | Rule::mutable => "static",
+        Rule::EOI => "end of file",
+        _ => return None,
+    })
+}
+
+/// Whether pest was still able to continue the expression in front of the
+/// error, but a `;` would have ended the statement there.
+fn expects_semicolon(positives: &[Rule]) -> bool {
+    positives.contains(&Rule::semi)
+        && positives
+            .iter()
+            .any(|rule| describe_rule(*rule) == Some("operator"))
+}
+
+/// Builds a message like "expected expression or block" from the rules pest
+/// tried at the position of the error.
+fn expected_message(positives: &[Rule]) -> String {
+    if expects_semicolon(positives) {
+        return "expected `;` after expression".to_string();
+    }
+
+    let mut expected: Vec<&str> = Vec::new();
+    for name in positives.iter().filter_map(|rule| describe_rule(*rule)) {
+        if !expected.contains(&name) {
+            expected.push(name);
+        }
+    }
+
+    // a name is one kind of expression
+    if expected.contains(&"expression") {
+        expected.retain(|name| *name != "identifier");
+    }
+
+    match expected.split_last() {
+        None => "syntax error".to_string(),
+        Some((last, [])) => format!("expected {}", last),
+        Some((last, rest)) => format!("expected {} or {}", rest.join(", "), last),
     }
 }
 
-fn parse_expr2(code: Pair<Rule>) -> Option<Expr> {
-    let mut iter = code.into_inner();
-    let inner = iter.next()?;
+/// Converts a pest error for `text`, which starts at `offset` in the file,
+/// into a `ParseError`.
+fn syntax_error(error: pest::error::Error<Rule>, text: &str, offset: usize) -> CompileError {
+    let span: Span = error.location.into();
+
+    let (message, span) = match &error.variant {
+        // point right behind the expression missing its `;`, not at the
+        // start of the next line
+        ErrorVariant::ParsingError { positives, .. } if expects_semicolon(positives) => {
+            let end = text[..span.start].trim_end().len();
+            (expected_message(positives), Span::new(end, end))
+        }
+        ErrorVariant::ParsingError { positives, .. } => (expected_message(positives), span),
+        ErrorVariant::CustomError { message } => (message.clone(), span),
+    };
+
+    CompileError::ParseError {
+        message,
+        span: Span::new(span.start + offset, span.end + offset),
+    }
+}
+
+/// Explains text skipped by `invalid_stmt` or `invalid_item` by parsing it
+/// again with the rule that failed to match it.
+fn explain_invalid(pair: Pair<Rule>, rule: Rule) -> CompileError {
+    let span: Span = pair.as_span().into();
+
+    match Parser::parse(rule, pair.as_str()) {
+        Err(error) => syntax_error(error, pair.as_str(), span.start),
+        // the rule only matches with the text following the skipped one
+        Ok(_) => CompileError::ParseError {
+            message: format!("unexpected `{}`", pair.as_str().trim()),
+            span,
+        },
+    }
+}
+
+/// Takes the next child of a rule, which the grammar should always provide.
+fn next_pair<'i>(
+    iter: &mut Pairs<'i, Rule>,
+    span: Span,
+    expected: &str,
+) -> ParseResult<Pair<'i, Rule>> {
+    iter.next().ok_or_else(|| CompileError::ParseError {
+        message: format!("expected {}", expected),
+        span,
+    })
+}
+
+fn parse_name(code: Pair<Rule>) -> Name {
+    Name::new(code.as_str().to_string(), code.as_span().into())
+}
+
+fn binary_expr(op: Rule, lhs: Expr, rhs: Expr) -> Expr {
+    let span = lhs.span.to(rhs.span);
+    let (lhs, rhs) = (Box::new(lhs), Box::new(rhs));
+
+    let op = match op {
+        Rule::or => return Expr::new(ExprKind::Logical(LogicalOperator::Or, lhs, rhs), span),
+        Rule::and => return Expr::new(ExprKind::Logical(LogicalOperator::And, lhs, rhs), span),
+        Rule::eq => Operator::Eq,
+        Rule::ne => Operator::Ne,
+        Rule::lt => Operator::Lt,
+        Rule::le => Operator::Le,
+        Rule::gt => Operator::Gt,
+        Rule::ge => Operator::Ge,
+        Rule::add => Operator::Add,
+        Rule::sub => Operator::Sub,
+        Rule::mul => Operator::Mul,
+        Rule::div => Operator::Div,
+        _ => unreachable!("{:?} is not a binary operator", op),
+    };
+
+    Expr::new(ExprKind::Binary(op, lhs, rhs), span)
+}
+
+fn unary_expr(op: Pair<Rule>, operand: Expr) -> Expr {
+    let span = Span::from(op.as_span()).to(operand.span);
+
+    let operand = Box::new(operand);
+    let kind = match op.as_rule() {
+        Rule::neg => ExprKind::Unary(UnaryOperator::Neg, operand),
+        Rule::not => ExprKind::Unary(UnaryOperator::Not, operand),
+        Rule::address_of => ExprKind::AddressOf(operand),
+        Rule::deref => ExprKind::Deref(operand),
+        _ => unreachable!("{:?} is not a unary operator", op),
+    };
+
+    Expr::new(kind, span)
+}
+
+fn parse_type_name(code: Pair<Rule>) -> ParseResult<TypeName> {
+    let span = code.as_span().into();
+    let inner = next_pair(&mut code.into_inner(), span, "type name")?;
+
+    let kind = match inner.as_rule() {
+        Rule::ident => TypeNameKind::Named(parse_name(inner)),
+        Rule::array_type => {
+            let mut iter = inner.into_inner();
+            let element = parse_type_name(next_pair(&mut iter, span, "element type")?)?;
+            let len = next_pair(&mut iter, span, "array length")?;
+            let len_span = len.as_span().into();
+
+            match parse_int_literal(len.as_str(), len_span)? {
+                ExprKind::IntLiteral(len, None) => TypeNameKind::Array(Box::new(element), len),
+                _ => {
+                    return Err(CompileError::ParseError {
+                        message: "array lengths cannot have a type suffix".to_string(),
+                        span: len_span,
+                    })
+                }
+            }
+        }
+        Rule::slice_type => {
+            let element = next_pair(&mut inner.into_inner(), span, "element type")?;
+            TypeNameKind::Slice(Box::new(parse_type_name(element)?))
+        }
+        Rule::pointer_type => {
+            let pointee = next_pair(&mut inner.into_inner(), span, "pointee type")?;
+            TypeNameKind::Pointer(Box::new(parse_type_name(pointee)?))
+        }
+        _ => unreachable!("{:?}", inner),
+    };
+
+    Ok(TypeName { kind, span })
+}
+
+fn cast_expr(operand: Expr, op: Pair<Rule>) -> ParseResult<Expr> {
+    let span = operand.span.to(op.as_span().into());
+    let op_span = op.as_span().into();
+    let ty = parse_type_name(next_pair(&mut op.into_inner(), op_span, "type")?)?;
+
+    Ok(Expr::new(ExprKind::Cast(Box::new(operand), ty), span))
+}
+
+fn member_expr(operand: Expr, op: Pair<Rule>) -> ParseResult<Expr> {
+    let span = operand.span.to(op.as_span().into());
+    let op_span = op.as_span().into();
+    let field = parse_name(next_pair(&mut op.into_inner(), op_span, "field name")?);
+
+    Ok(Expr::new(ExprKind::Field(Box::new(operand), field), span))
+}
+
+fn index_expr(operand: Expr, op: Pair<Rule>) -> ParseResult<Expr> {
+    let span = operand.span.to(op.as_span().into());
+    let op_span = op.as_span().into();
+    let index = parse_expr(next_pair(&mut op.into_inner(), op_span, "index")?)?;
+
+    Ok(Expr::new(
+        ExprKind::Index(Box::new(operand), Box::new(index)),
+        span,
+    ))
+}
+
+fn postfix_expr(operand: Expr, op: Pair<Rule>) -> ParseResult<Expr> {
+    match op.as_rule() {
+        Rule::cast => cast_expr(operand, op),
+        Rule::member => member_expr(operand, op),
+        Rule::index => index_expr(operand, op),
+        _ => unreachable!("{:?}", op),
+    }
+}
+
+/// Splits an integer literal like `0xff_u8` into its value and the type of
+/// its suffix. The literal may start with a `0x`, `0o` or `0b` radix prefix
+/// and separate its digits with `_`.
+fn parse_int_literal(literal: &str, span: Span) -> ParseResult<ExprKind> {
+    let error = |message: String| CompileError::ParseError { message, span };
 
-    match inner.as_rule() {
-        Rule::expr3 => parse_expr3(inner),
+    let (radix, name, rest) = match literal.get(..2) {
+        Some("0x") => (16, "hexadecimal", &literal[2..]),
+        Some("0o") => (8, "octal", &literal[2..]),
+        Some("0b") => (2, "binary", &literal[2..]),
+        _ => (10, "decimal", literal),
+    };
+
+    let end = rest
+        .find(|c: char| !c.is_digit(radix) && c != '_')
+        .unwrap_or(rest.len());
+    let (digits, suffix) = rest.split_at(end);
+    let digits = digits.replace('_', "");
+
+    if let Some(digit) = suffix.chars().next().filter(char::is_ascii_digit) {
+        return Err(error(format!(
+            "invalid digit `{}` in {} literal",
+            digit, name
+        )));
+    }
+    if digits.is_empty() {
+        return Err(error(format!("{} literal has no digits", name)));
+    }
+
+    let ty = match suffix {
+        "" => None,
+        suffix => match Type::from_name(suffix) {
+            Some(Type::Int(int)) => Some(int),
+            _ => {
+                return Err(error(format!(
+                    "invalid suffix `{}` for integer literal",
+                    suffix
+                )))
+            }
+        },
+    };
+
+    match u64::from_str_radix(&digits, radix) {
+        Ok(value) => Ok(ExprKind::IntLiteral(value, ty)),
+        Err(_) => Err(error(format!("integer literal `{}` is too large", literal))),
+    }
+}
+
+fn is_hex(digits: &str) -> bool {
+    !digits.is_empty() && digits.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+/// Replaces the escape sequences in the body of a string or character
+/// literal, which starts at `offset` in the file. These are one of `\n`,
+/// `\r`, `\t`, `\0`, `\\`, `\'` and `\"`, an ASCII character like `\x41` or
+/// a Unicode scalar value like `\u{1F600}`.
+fn unescape(body: &str, offset: usize) -> ParseResult<String> {
+    let mut string = String::with_capacity(body.len());
+    let mut chars = body.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        if c != '\\' {
+            string.push(c);
+            continue;
+        }
+
+        // the grammar only accepts a backslash followed by another character
+        let (_, escaped) = chars.next().expect("escape sequence is incomplete");
+        let rest = &body[i + 2..];
+
+        let (c, len) = match escaped {
+            'n' => (Some('\n'), 2),
+            'r' => (Some('\r'), 2),
+            't' => (Some('\t'), 2),
+            '0' => (Some('\0'), 2),
+            '\\' | '\'' | '"' => (Some(escaped), 2),
+            'x' => match rest.get(..2).filter(|digits| is_hex(digits)) {
+                // larger bytes are not valid UTF-8 on their own
+                Some(digits) => {
+                    let byte = u8::from_str_radix(digits, 16).expect("digits are hexadecimal");
+                    (Some(byte as char).filter(char::is_ascii), 4)
+                }
+                None => (None, 2),
+            },
+            'u' => match rest.strip_prefix('{').and_then(|rest| rest.split_once('}')) {
+                Some((digits, _)) => {
+                    let c = Some(digits)
+                        .filter(|digits| is_hex(digits) && digits.len() <= 6)
+                        .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+                        .and_then(char::from_u32);
+                    (c, 4 + digits.len())
+                }
+                None => (None, 2),
+            },
+            _ => (None, 1 + escaped.len_utf8()),
+        };
+
+        let c = c.ok_or_else(|| {
+            let escape = &body[i..i + len];
+            let message = match escaped {
+                'x' if len == 4 => format!("`{}` is not an ASCII character", escape),
+                'x' => "expected two hexadecimal digits after `\\x`".to_string(),
+                'u' if len > 2 => format!("`{}` is not a Unicode scalar value", escape),
+                'u' => "expected `{` and `}` around the digits of `\\u`".to_string(),
+                _ => format!("unknown escape sequence `{}`", escape),
+            };
+
+            CompileError::ParseError {
+                message,
+                span: Span::new(offset + i, offset + i + len),
+            }
+        })?;
+
+        string.push(c);
+        while chars.next_if(|(j, _)| *j < i + len).is_some() {}
+    }
+
+    Ok(string)
+}
+
+/// Body of a quoted literal and the offset of the body in the file.
+fn literal_body(literal: &str, span: Span) -> (&str, usize) {
+    (&literal[1..literal.len() - 1], span.start + 1)
+}
+
+fn parse_char(literal: &str, span: Span) -> ParseResult<char> {
+    let (body, offset) = literal_body(literal, span);
+    let unescaped = unescape(body, offset)?;
+    let mut chars = unescaped.chars();
+
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err(CompileError::ParseError {
+            message: "character literals must contain exactly one character".to_string(),
+            span,
+        }),
+    }
+}
+
+fn parse_term(inner: Pair<Rule>) -> ParseResult<Expr> {
+    let span: Span = inner.as_span().into();
+
+    let kind = match inner.as_rule() {
+        Rule::number => parse_int_literal(inner.as_str(), span)?,
+        Rule::float => match inner.as_str().parse() {
+            Ok(float) => ExprKind::FloatLiteral(float),
+            Err(_) => {
+                return Err(CompileError::ParseError {
+                    message: format!("invalid float literal `{}`", inner.as_str()),
+                    span,
+                })
+            }
+        },
+        Rule::boolean => ExprKind::BoolLiteral(inner.as_str() == "true"),
+        Rule::null => ExprKind::Null,
+        Rule::string => {
+            let (body, offset) = literal_body(inner.as_str(), span);
+            ExprKind::StringLiteral(unescape(body, offset)?)
+        }
+        Rule::character => ExprKind::CharLiteral(parse_char(inner.as_str(), span)?),
+        Rule::ident => ExprKind::Name(parse_name(inner)),
+        Rule::expr => return parse_expr(inner),
         Rule::call_expr => {
             let mut iter = inner.into_inner();
-            let name = Name::new(iter.next()?.as_str().to_string());
+            let name = parse_name(next_pair(&mut iter, span, "function name")?);
             let mut args = Vec::new();
 
             for pair in iter {
                 args.push(parse_expr(pair)?);
             }
 
-            Some(Expr::Call(name, args))
+            ExprKind::Call(name, args)
         }
-        _ => unreachable!(),
-    }
+        Rule::struct_literal => {
+            let mut iter = inner.into_inner();
+            let name = parse_name(next_pair(&mut iter, span, "struct name")?);
+            let mut fields = Vec::new();
+
+            for pair in iter {
+                let field_span = pair.as_span().into();
+                let mut field = pair.into_inner();
+                let field_name = parse_name(next_pair(&mut field, field_span, "field name")?);
+                let value = parse_expr(next_pair(&mut field, field_span, "expression")?)?;
+                fields.push((field_name, value));
+            }
+
+            ExprKind::StructLiteral(name, fields)
+        }
+        Rule::array_literal => ExprKind::ArrayLiteral(
+            inner
+                .into_inner()
+                .map(parse_expr)
+                .collect::<ParseResult<_>>()?,
+        ),
+        Rule::variant_expr => {
+            let mut iter = inner.into_inner();
+            let name = parse_name(next_pair(&mut iter, span, "enum name")?);
+            let variant = parse_name(next_pair(&mut iter, span, "variant name")?);
+            let values = iter.map(parse_expr).collect::<ParseResult<_>>()?;
+
+            ExprKind::Variant(name, variant, values)
+        }
+        Rule::match_expr => {
+            let mut iter = inner.into_inner();
+            let scrutinee = parse_expr(next_pair(&mut iter, span, "expression")?)?;
+            let arms = iter.map(parse_arm).collect::<ParseResult<_>>()?;
+
+            ExprKind::Match(Box::new(scrutinee), arms)
+        }
+        _ => unreachable!("{:?}", inner),
+    };
+
+    Ok(Expr::new(kind, span))
 }
 
-fn parse_expr1(code: Pair<Rule>) -> Option<Expr> {
-    let mut iter = code.into_inner();
-    let expr2 = parse_expr2(iter.next()?)?;
-    let op = iter.next();
-
-    Some(match op.map(|op| op.as_str()) {
-        Some("*") => Expr::mul(expr2, parse_expr1(iter.next()?)?),
-        Some("/") => Expr::div(expr2, parse_expr1(iter.next()?)?),
-        None => expr2,
-        _ => unreachable!(),
-    })
+fn parse_pattern(code: Pair<Rule>) -> ParseResult<Pattern> {
+    let span = code.as_span().into();
+    let inner = next_pair(&mut code.into_inner(), span, "pattern")?;
+
+    let kind = match inner.as_rule() {
+        Rule::wildcard => PatternKind::Wildcard,
+        Rule::ident => PatternKind::Binding(parse_name(inner)),
+        Rule::variant_pattern => {
+            let mut iter = inner.into_inner();
+            let name = parse_name(next_pair(&mut iter, span, "enum name")?);
+            let variant = parse_name(next_pair(&mut iter, span, "variant name")?);
+            let fields = iter.map(parse_pattern).collect::<ParseResult<_>>()?;
+
+            PatternKind::Variant(name, variant, fields)
+        }
+        Rule::literal_pattern => {
+            let mut iter = inner.into_inner();
+            let first = next_pair(&mut iter, span, "literal")?;
+
+            let literal = match first.as_rule() {
+                Rule::neg => {
+                    let literal = parse_term(next_pair(&mut iter, span, "literal")?)?;
+                    unary_expr(first, literal)
+                }
+                _ => parse_term(first)?,
+            };
+
+            PatternKind::Literal(literal)
+        }
+        _ => unreachable!("{:?}", inner),
+    };
+
+    Ok(Pattern { kind, span })
 }
 
-fn parse_expr(code: Pair<Rule>) -> Option<Expr> {
+/// Parses an arm of a `match`. Expressions have no way to report several
+/// errors, so only the first syntax error in the block of an arm is kept.
+fn parse_arm(code: Pair<Rule>) -> ParseResult<Arm> {
+    let span = code.as_span().into();
     let mut iter = code.into_inner();
-    let expr1 = parse_expr1(iter.next()?)?;
-    let op = iter.next();
-
-    Some(match op.map(|op| op.as_str()) {
-        Some("+") => Expr::add(expr1, parse_expr(iter.next()?)?),
-        Some("-") => Expr::sub(expr1, parse_expr(iter.next()?)?),
-        None => expr1,
-        _ => unreachable!(),
+    let pattern = parse_pattern(next_pair(&mut iter, span, "pattern")?)?;
+    let body = next_pair(&mut iter, span, "expression")?;
+
+    let (body, value) = match body.as_rule() {
+        Rule::block => {
+            let mut errors = Vec::new();
+            let body = parse_block(body, &mut errors);
+
+            if let Some(error) = errors.into_iter().next() {
+                return Err(error);
+            }
+
+            (body, None)
+        }
+        _ => (Vec::new(), Some(parse_expr(body)?)),
+    };
+
+    Ok(Arm {
+        pattern,
+        body,
+        value,
+        span,
     })
 }
 
-fn parse_assign(code: Pair<Rule>) -> Option<Stmt> {
+fn parse_expr(code: Pair<Rule>) -> ParseResult<Expr> {
+    pratt_parser()
+        .map_primary(parse_term)
+        .map_prefix(|op, operand| Ok(unary_expr(op, operand?)))
+        .map_postfix(|operand, op| postfix_expr(operand?, op))
+        .map_infix(|lhs, op, rhs| Ok(binary_expr(op.as_rule(), lhs?, rhs?)))
+        .parse(code.into_inner())
+}
+
+fn parse_assign(code: Pair<Rule>) -> ParseResult<Stmt> {
+    let span = code.as_span().into();
     let mut iter = code.into_inner();
-    let ident = Name::new(iter.next()?.as_str().to_string());
-    let expr = parse_expr(iter.next()?)?;
-    Some(Stmt::Assign(ident, Box::new(expr)))
+    let place = parse_expr(next_pair(&mut iter, span, "assignment target")?)?;
+    let expr = parse_expr(next_pair(&mut iter, span, "expression")?)?;
+    Ok(Stmt::new(
+        StmtKind::Assign(Box::new(place), Box::new(expr)),
+        span,
+    ))
 }
 
-fn parse_let(code: Pair<Rule>) -> Option<Stmt> {
+fn parse_let(code: Pair<Rule>) -> ParseResult<Stmt> {
+    let span = code.as_span().into();
     let mut iter = code.into_inner();
-    let ident = Name::new(iter.next()?.as_str().to_string());
+    let ident = parse_name(next_pair(&mut iter, span, "variable name")?);
 
-    let expr = iter.next()?;
+    let mut next = next_pair(&mut iter, span, "expression")?;
+    let ty = if next.as_rule() == Rule::type_name {
+        let ty = parse_type_name(next)?;
+        next = next_pair(&mut iter, span, "expression")?;
+        Some(ty)
+    } else {
+        None
+    };
 
-    Some(Stmt::Let(ident, parse_expr(expr)?))
+    Ok(Stmt::new(StmtKind::Let(ident, ty, parse_expr(next)?), span))
 }
 
-fn parse_return(code: Pair<Rule>) -> Option<Stmt> {
-    Some(Stmt::Return(parse_expr(code.into_inner().next()?)?))
+fn parse_return(code: Pair<Rule>) -> ParseResult<Stmt> {
+    let span = code.as_span().into();
+    let expr = code.into_inner().next().map(parse_expr).transpose()?;
+    Ok(Stmt::new(StmtKind::Return(expr), span))
 }
 
-fn parse_if(code: Pair<Rule>) -> Option<Stmt> {
+fn parse_if(code: Pair<Rule>, errors: &mut Errors) -> ParseResult<Stmt> {
+    let span = code.as_span().into();
     let mut iter = code.into_inner();
-    let condition = parse_expr(iter.next()?)?;
-    let body = parse_block(iter.next()?)?;
-    let else_body = parse_block(iter.next()?)?;
+    let condition = parse_expr(next_pair(&mut iter, span, "condition")?)?;
+    let body = parse_block(next_pair(&mut iter, span, "`if` body")?, errors);
+
+    // `else if` is an `else` block holding nothing but the nested `if`
+    let else_body = match iter.next() {
+        Some(pair) if pair.as_rule() == Rule::if_expr => Some(vec![parse_if(pair, errors)?]),
+        Some(pair) => Some(parse_block(pair, errors)),
+        None => None,
+    };
 
-    Some(Stmt::If(condition, body, else_body))
+    Ok(Stmt::new(StmtKind::If(condition, body, else_body), span))
 }
 
-fn parse_for(code: Pair<Rule>) -> Option<Stmt> {
+fn parse_for(code: Pair<Rule>, errors: &mut Errors) -> ParseResult<Stmt> {
+    let span = code.as_span().into();
     let mut iter = code.into_inner();
-    let stmt = parse_let(iter.next()?)?;
-    let condition = parse_expr(iter.next()?)?;
-    let step = parse_expr(iter.next()?)?;
-    let body = parse_block(iter.next()?)?;
+    let init = parse_inner_statement(next_pair(&mut iter, span, "loop initializer")?, errors)?;
+    let condition = parse_expr(next_pair(&mut iter, span, "loop condition")?)?;
+    let step = parse_inner_statement(next_pair(&mut iter, span, "loop step")?, errors)?;
+    let body = parse_block(next_pair(&mut iter, span, "loop body")?, errors);
+
+    Ok(Stmt::new(
+        StmtKind::For(Box::new(init), condition, Box::new(step), body),
+        span,
+    ))
+}
+
+fn parse_while(code: Pair<Rule>, errors: &mut Errors) -> ParseResult<Stmt> {
+    let span = code.as_span().into();
+    let mut iter = code.into_inner();
+    let condition = parse_expr(next_pair(&mut iter, span, "loop condition")?)?;
+    let body = parse_block(next_pair(&mut iter, span, "loop body")?, errors);
+
+    Ok(Stmt::new(StmtKind::While(condition, body), span))
+}
+
+fn parse_loop(code: Pair<Rule>, errors: &mut Errors) -> ParseResult<Stmt> {
+    let span = code.as_span().into();
+    let body = parse_block(
+        next_pair(&mut code.into_inner(), span, "loop body")?,
+        errors,
+    );
 
-    Some(Stmt::For(Box::new(stmt), condition, step, body))
+    Ok(Stmt::new(StmtKind::Loop(body), span))
 }
 
-fn parse_statement(code: Pair<Rule>) -> Option<Stmt> {
-    let expr = code.into_inner().next()?;
+fn parse_statement(code: Pair<Rule>, errors: &mut Errors) -> ParseResult<Stmt> {
+    let span = code.as_span().into();
+    parse_inner_statement(
+        next_pair(&mut code.into_inner(), span, "statement")?,
+        errors,
+    )
+}
+
+/// Parses the rule wrapped by a `stmt`, which is also how the initializer
+/// and the step of a `for` loop are written.
+fn parse_inner_statement(expr: Pair<Rule>, errors: &mut Errors) -> ParseResult<Stmt> {
     match expr.as_rule() {
-        Rule::expr => Some(Stmt::Expr(parse_expr(expr)?)),
+        Rule::expr => {
+            let span = expr.as_span().into();
+            Ok(Stmt::new(StmtKind::Expr(parse_expr(expr)?), span))
+        }
+        Rule::match_expr => {
+            let span = expr.as_span().into();
+            Ok(Stmt::new(StmtKind::Expr(parse_term(expr)?), span))
+        }
         Rule::let_expr => parse_let(expr),
         Rule::return_expr => parse_return(expr),
-        Rule::if_expr => parse_if(expr),
-        Rule::for_expr => parse_for(expr),
+        Rule::if_expr => parse_if(expr, errors),
+        Rule::for_expr => parse_for(expr, errors),
+        Rule::while_expr => parse_while(expr, errors),
+        Rule::loop_expr => parse_loop(expr, errors),
+        Rule::break_expr => Ok(Stmt::new(StmtKind::Break, expr.as_span().into())),
+        Rule::continue_expr => Ok(Stmt::new(StmtKind::Continue, expr.as_span().into())),
         Rule::assign_expr => parse_assign(expr),
         _ => unreachable!("{:?}", expr),
     }
 }
 
-fn parse_block(code: Pair<Rule>) -> Option<Vec<Stmt>> {
+/// Parses the statements of a block. Statements that fail to parse are
+/// reported and left out, so the rest of the block is still checked.
+fn parse_block(code: Pair<Rule>, errors: &mut Errors) -> Vec<Stmt> {
     let mut stmts = Vec::new();
 
     for pair in code.into_inner() {
-        let stmt = parse_statement(pair);
-        stmts.push(stmt?);
+        let stmt = match pair.as_rule() {
+            // documents nothing, so it is skipped like any other comment
+            Rule::doc_comment => continue,
+            Rule::invalid_stmt => Err(explain_invalid(pair, Rule::stmt)),
+            _ => parse_statement(pair, errors),
+        };
+
+        match stmt {
+            Ok(stmt) => stmts.push(stmt),
+            Err(error) => errors.push(error),
+        }
     }
 
-    Some(stmts)
+    stmts
 }
 
-fn parse_function(code: Pair<Rule>) -> Option<Function> {
+fn parse_param(code: Pair<Rule>) -> ParseResult<Param> {
+    let span = code.as_span().into();
     let mut iter = code.into_inner();
 
-    let name = Name::new(iter.next()?.as_str().to_string());
+    let name = parse_name(next_pair(&mut iter, span, "parameter name")?);
+    let ty = parse_type_name(next_pair(&mut iter, span, "parameter type")?)?;
+
+    Ok(Param { name, ty })
+}
+
+fn parse_field(code: Pair<Rule>) -> ParseResult<Field> {
+    let span = code.as_span().into();
+    let mut iter = code.into_inner();
+
+    let name = parse_name(next_pair(&mut iter, span, "field name")?);
+    let ty = parse_type_name(next_pair(&mut iter, span, "field type")?)?;
+
+    Ok(Field { name, ty })
+}
+
+/// Joins the `///` comments in front of an item, one line per comment.
+fn parse_doc_comments(iter: &mut Pairs<Rule>) -> Option<String> {
+    let mut lines = Vec::new();
+
+    while let Some(pair) = iter
+        .peek()
+        .filter(|pair| pair.as_rule() == Rule::doc_comment)
+    {
+        iter.next();
+
+        let line = pair.as_str()["///".len()..].trim_end();
+        lines.push(line.strip_prefix(' ').unwrap_or(line).to_string());
+    }
+
+    if lines.is_empty() {
+        None
+    } else {
+        Some(lines.join("\n"))
+    }
+}
+
+fn parse_function(code: Pair<Rule>, errors: &mut Errors) -> ParseResult<Function> {
+    let span = code.as_span().into();
+    let mut iter = code.into_inner();
+
+    let doc = parse_doc_comments(&mut iter);
+    let name = parse_name(next_pair(&mut iter, span, "function name")?);
 
     let mut args = Vec::new();
 
+    let mut ret = None;
+
     let mut body = None;
 
     for pair in iter {
-        if pair.as_rule() == Rule::block {
-            body = Some(pair);
-            break;
+        match pair.as_rule() {
+            Rule::param => args.push(parse_param(pair)?),
+            Rule::type_name => ret = Some(parse_type_name(pair)?),
+            Rule::block => {
+                body = Some(pair);
+                break;
+            }
+            _ => unreachable!("{:?}", pair),
         }
-        args.push(Name::new(pair.as_str().to_string()));
     }
 
-    let body = parse_block(body.unwrap())?;
+    let reported = errors.len();
+    let body = match body {
+        Some(body) => parse_block(body, errors),
+        None => {
+            return Err(CompileError::ParseError {
+                message: "expected function body".to_string(),
+                span,
+            })
+        }
+    };
 
-    Some(Function { name, args, body })
+    Ok(Function {
+        doc,
+        name,
+        args,
+        ret,
+        body,
+        skipped: errors[reported..]
+            .iter()
+            .map(|error| error.span())
+            .min_by_key(|span| span.start),
+        span,
+    })
 }
 
-pub fn parse_program(code: Pair<Rule>) -> Option<Program> {
+fn parse_extern_function(code: Pair<Rule>) -> ParseResult<ExternFunction> {
+    let span = code.as_span().into();
+    let mut iter = code.into_inner();
+
+    let doc = parse_doc_comments(&mut iter);
+    let name = parse_name(next_pair(&mut iter, span, "function name")?);
+
+    let mut args = Vec::new();
+    let mut variadic = false;
+    let mut ret = None;
+
+    for pair in iter {
+        match pair.as_rule() {
+            Rule::param => args.push(parse_param(pair)?),
+            Rule::variadic => variadic = true,
+            Rule::type_name => ret = Some(parse_type_name(pair)?),
+            Rule::semi => {}
+            _ => unreachable!("{:?}", pair),
+        }
+    }
+
+    Ok(ExternFunction {
+        doc,
+        name,
+        args,
+        variadic,
+        ret,
+        span,
+    })
+}
+
+fn parse_struct(code: Pair<Rule>) -> ParseResult<Struct> {
+    let span = code.as_span().into();
+    let mut iter = code.into_inner();
+
+    let doc = parse_doc_comments(&mut iter);
+    let name = parse_name(next_pair(&mut iter, span, "struct name")?);
+    let fields = iter.map(parse_field).collect::<ParseResult<_>>()?;
+
+    Ok(Struct {
+        doc,
+        name,
+        fields,
+        span,
+    })
+}
+
+fn parse_variant(code: Pair<Rule>) -> ParseResult<Variant> {
+    let span = code.as_span().into();
+    let mut iter = code.into_inner();
+
+    let name = parse_name(next_pair(&mut iter, span, "variant name")?);
+    let fields = iter.map(parse_type_name).collect::<ParseResult<_>>()?;
+
+    Ok(Variant { name, fields })
+}
+
+fn parse_enum(code: Pair<Rule>) -> ParseResult<Enum> {
+    let span = code.as_span().into();
+    let mut iter = code.into_inner();
+
+    let doc = parse_doc_comments(&mut iter);
+    let name = parse_name(next_pair(&mut iter, span, "enum name")?);
+    let variants = iter.map(parse_variant).collect::<ParseResult<_>>()?;
+
+    Ok(Enum {
+        doc,
+        name,
+        variants,
+        span,
+    })
+}
+
+fn parse_const(code: Pair<Rule>) -> ParseResult<Const> {
+    let span = code.as_span().into();
+    let mut iter = code.into_inner();
+
+    let doc = parse_doc_comments(&mut iter);
+    let name = parse_name(next_pair(&mut iter, span, "constant name")?);
+
+    let mut next = next_pair(&mut iter, span, "expression")?;
+    let ty = match next.as_rule() {
+        Rule::type_name => {
+            let ty = parse_type_name(next)?;
+            next = next_pair(&mut iter, span, "expression")?;
+            Some(ty)
+        }
+        _ => None,
+    };
+    let value = parse_expr(next)?;
+
+    Ok(Const {
+        doc,
+        name,
+        ty,
+        value,
+        span,
+    })
+}
+
+fn parse_static(code: Pair<Rule>) -> ParseResult<Static> {
+    let span = code.as_span().into();
+    let mut iter = code.into_inner();
+
+    let doc = parse_doc_comments(&mut iter);
+
+    let mut next = next_pair(&mut iter, span, "static name")?;
+    let mutable = next.as_rule() == Rule::mutable;
+    if mutable {
+        next = next_pair(&mut iter, span, "static name")?;
+    }
+
+    let name = parse_name(next);
+    let ty = parse_type_name(next_pair(&mut iter, span, "type")?)?;
+    let value = parse_expr(next_pair(&mut iter, span, "expression")?)?;
+
+    Ok(Static {
+        doc,
+        name,
+        mutable,
+        ty,
+        value,
+        span,
+    })
+}
+
+pub fn parse_program(code: Pair<Rule>, errors: &mut Errors) -> Program {
     let mut functions = Vec::new();
+    let mut externs = Vec::new();
+    let mut structs = Vec::new();
+    let mut enums = Vec::new();
+    let mut consts = Vec::new();
+    let mut statics = Vec::new();
 
     for pair in code.into_inner() {
-        if pair.as_rule() == Rule::func {
-            functions.push(parse_function(pair)?);
+        let result = match pair.as_rule() {
+            Rule::func => parse_function(pair, errors).map(|function| functions.push(function)),
+            Rule::extern_func => parse_extern_function(pair).map(|function| externs.push(function)),
+            Rule::struct_decl => parse_struct(pair).map(|def| structs.push(def)),
+            Rule::enum_decl => parse_enum(pair).map(|def| enums.push(def)),
+            Rule::const_decl => parse_const(pair).map(|def| consts.push(def)),
+            Rule::static_decl => parse_static(pair).map(|def| statics.push(def)),
+            Rule::invalid_item => Err(explain_invalid(pair, Rule::single_item)),
+            _ => Ok(()),
+        };
+
+        if let Err(error) = result {
+            errors.push(error);
+        }
+    }
+
+    Program {
+        functions,
+        externs,
+        structs,
+        enums,
+        consts,
+        statics,
+    }
+}
+
+/// Runs the pest grammar over the whole file and builds the gast from it.
+/// Syntax errors do not stop the parser, it skips to the next statement or
+/// function and returns every error along with what it could parse.
+pub fn parse_source(code: &str) -> (Program, Vec<CompileError>) {
+    let mut errors = Vec::new();
+
+    let program = match Parser::parse(Rule::program, code) {
+        Ok(mut pairs) => {
+            let program = pairs.next().expect("program rule always produces a pair");
+            parse_program(program, &mut errors)
+        }
+        Err(error) => {
+            errors.push(syntax_error(error, code, 0));
+            Program {
+                functions: Vec::new(),
+                externs: Vec::new(),
+                structs: Vec::new(),
+                enums: Vec::new(),
+                consts: Vec::new(),
+                statics: Vec::new(),
+            }
+        }
+    };
+
+    errors.sort_by_key(|error| error.span().start);
+    (program, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use pest::Parser as _;
+
+    use super::*;
+    use crate::internal_representations::ast::IntType;
+
+    /// Value of an expression built from integer literals and arithmetic.
+    fn eval(expr: &Expr) -> i64 {
+        match &expr.kind {
+            ExprKind::IntLiteral(value, _) => *value as i64,
+            ExprKind::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (eval(lhs), eval(rhs));
+                match op {
+                    Operator::Add => lhs + rhs,
+                    Operator::Sub => lhs - rhs,
+                    Operator::Mul => lhs * rhs,
+                    Operator::Div => lhs / rhs,
+                    _ => panic!("{:?} is not arithmetic", op),
+                }
+            }
+            _ => panic!("{:?} is not arithmetic", expr),
         }
     }
 
-    Some(Program { functions })
+    fn eval_source(code: &str) -> i64 {
+        let mut pairs = Parser::parse(Rule::expr, code).expect("expression parses");
+        let expr = parse_expr(pairs.next().expect("expr rule produces a pair"));
+        eval(&expr.expect("expression is valid"))
+    }
+
+    #[test]
+    fn operators_are_left_associative() {
+        assert_eq!(eval_source("10 - 3 - 2"), 5);
+        assert_eq!(eval_source("16 / 4 / 2"), 2);
+    }
+
+    #[test]
+    fn operators_bind_by_precedence() {
+        assert_eq!(eval_source("2 * 3 + 4"), 10);
+        assert_eq!(eval_source("2 + 3 * 4"), 14);
+        assert_eq!(eval_source("(2 + 3) * 4"), 20);
+    }
+
+    fn parse_stmt_source(code: &str) -> Stmt {
+        let mut pairs = Parser::parse(Rule::stmt, code).expect("statement parses");
+        let mut errors = Vec::new();
+        let stmt = parse_statement(
+            pairs.next().expect("stmt rule produces a pair"),
+            &mut errors,
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+        stmt.expect("statement is valid")
+    }
+
+    /// Whether the statement declares or assigns a variable, and its name.
+    fn binding(code: &str) -> (&'static str, String) {
+        match parse_stmt_source(code).kind {
+            StmtKind::Let(name, ..) => ("let", name.name),
+            StmtKind::Assign(place, _) => match place.kind {
+                ExprKind::Name(name) => ("assign", name.name),
+                kind => panic!("{:?} is not a variable", kind),
+            },
+            kind => panic!("{:?} binds no variable", kind),
+        }
+    }
+
+    #[test]
+    fn keywords_end_at_a_word_boundary() {
+        assert_eq!(binding("letter = 5;"), ("assign", "letter".to_string()));
+        assert_eq!(binding("format = 5;"), ("assign", "format".to_string()));
+        assert_eq!(binding("iffy = 5;"), ("assign", "iffy".to_string()));
+        assert_eq!(
+            binding("breakfast = 5;"),
+            ("assign", "breakfast".to_string())
+        );
+        assert_eq!(
+            binding("structure = 5;"),
+            ("assign", "structure".to_string())
+        );
+        assert_eq!(binding("matches = 5;"), ("assign", "matches".to_string()));
+        assert_eq!(binding("let ter = 5;"), ("let", "ter".to_string()));
+    }
+
+    /// Value and suffix of an integer literal, or the message of its error.
+    fn int_literal(literal: &str) -> Result<(u64, Option<IntType>), String> {
+        match parse_int_literal(literal, Span::default()) {
+            Ok(ExprKind::IntLiteral(value, suffix)) => Ok((value, suffix)),
+            Ok(kind) => panic!("{:?} is not an integer literal", kind),
+            Err(error) => Err(error.to_string()),
+        }
+    }
+
+    #[test]
+    fn integer_literals_have_a_radix_separators_and_a_suffix() {
+        assert_eq!(int_literal("0xff"), Ok((255, None)));
+        assert_eq!(int_literal("0o17"), Ok((15, None)));
+        assert_eq!(int_literal("0b1010"), Ok((10, None)));
+        assert_eq!(int_literal("1_000_000"), Ok((1_000_000, None)));
+        assert_eq!(int_literal("0x_ff_u8"), Ok((255, Some(IntType::U8))));
+        assert_eq!(int_literal("42i32"), Ok((42, Some(IntType::I32))));
+    }
+
+    #[test]
+    fn reports_invalid_integer_literals() {
+        let error = |message: &str| Err(message.to_string());
+
+        assert_eq!(
+            int_literal("0b102"),
+            error("invalid digit `2` in binary literal")
+        );
+        assert_eq!(
+            int_literal("10u7"),
+            error("invalid suffix `u7` for integer literal")
+        );
+        assert_eq!(
+            int_literal("0x"),
+            error("hexadecimal literal has no digits")
+        );
+        assert_eq!(
+            int_literal("18446744073709551616"),
+            error("integer literal `18446744073709551616` is too large")
+        );
+    }
+
+    /// A string or character literal, or the message of its error.
+    fn literal(code: &str) -> Result<ExprKind, String> {
+        let mut pairs = Parser::parse(Rule::expr, code).expect("literal parses");
+        parse_expr(pairs.next().expect("expr rule produces a pair"))
+            .map(|expr| expr.kind)
+            .map_err(|error| error.to_string())
+    }
+
+    #[test]
+    fn decodes_escape_sequences() {
+        let string = |value: &str| Ok(ExprKind::StringLiteral(value.to_string()));
+
+        assert_eq!(literal(r#""a\n\r\t\\\"b""#), string("a\n\r\t\\\"b"));
+        assert_eq!(literal(r#""\x41\u{1F600}\0!""#), string("A\u{1F600}\0!"));
+        assert_eq!(literal(r"'\''"), Ok(ExprKind::CharLiteral('\'')));
+        assert_eq!(literal(r"'\u{e9}'"), Ok(ExprKind::CharLiteral('é')));
+    }
+
+    #[test]
+    fn reports_invalid_escape_sequences() {
+        let error = |message: &str| Err(message.to_string());
+
+        assert_eq!(literal(r#""\q""#), error(r"unknown escape sequence `\q`"));
+        assert_eq!(
+            literal(r#""\x80""#),
+            error(r"`\x80` is not an ASCII character")
+        );
+        assert_eq!(
+            literal(r#""\u{d800}""#),
+            error(r"`\u{d800}` is not a Unicode scalar value")
+        );
+        assert_eq!(
+            literal("'ab'"),
+            error("character literals must contain exactly one character")
+        );
+    }
+
+    /// Name of the static declared by the code and whether it is mutable.
+    fn static_decl(code: &str) -> (String, bool) {
+        let (program, errors) = parse_source(code);
+        assert!(errors.is_empty(), "{:?}", errors);
+        let decl = &program.statics[0];
+        (decl.name.name.clone(), decl.mutable)
+    }
+
+    #[test]
+    fn mut_ends_at_a_word_boundary() {
+        assert_eq!(
+            static_decl("static mutex: i64 = 0;"),
+            ("mutex".to_string(), false)
+        );
+        assert_eq!(
+            static_decl("static mut ex: i64 = 0;"),
+            ("ex".to_string(), true)
+        );
+    }
 }
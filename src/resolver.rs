@@ -0,0 +1,639 @@
+use std::collections::HashMap;
+
+use crate::{
+    builtins::Builtin,
+    diagnostics::{Diagnostic, Level, Span},
+    error::CompileError,
+    internal_representations::{
+        ast::{BindingId, Type},
+        gast::{
+            Arm, Enum, Expr, ExprKind, Function, Name, Pattern, PatternKind, Program, Stmt,
+            StmtKind, Struct,
+        },
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Parameter,
+    Local,
+    Constant,
+    Static { mutable: bool },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub span: Span,
+    pub used: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionSymbol {
+    pub params: usize,
+    pub span: Span,
+}
+
+/// Side table produced by name resolution. Every `Name` node of a variable,
+/// whether it declares the binding or refers to it, is keyed by its span.
+#[derive(Debug, Default)]
+pub struct SymbolTable {
+    pub symbols: Vec<Symbol>,
+    pub functions: HashMap<String, FunctionSymbol>,
+    resolutions: HashMap<Span, BindingId>,
+}
+
+impl SymbolTable {
+    pub fn symbol(&self, id: BindingId) -> &Symbol {
+        &self.symbols[id.0]
+    }
+
+    pub fn binding(&self, name: &Name) -> Option<BindingId> {
+        self.resolutions.get(&name.span).copied()
+    }
+
+    pub fn function(&self, name: &str) -> Option<&FunctionSymbol> {
+        self.functions.get(name)
+    }
+}
+
+pub struct Resolution {
+    pub symbols: SymbolTable,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Resolution {
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.level == Level::Error)
+    }
+}
+
+struct Resolver {
+    symbols: SymbolTable,
+    scopes: Vec<HashMap<String, BindingId>>,
+    /// Constants and statics, visible everywhere unless a local shadows them.
+    globals: HashMap<String, BindingId>,
+    /// Number of loops around the statement being resolved.
+    loop_depth: usize,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Resolver {
+    fn error(&mut self, error: CompileError) {
+        self.diagnostics.push(error.to_diagnostic());
+    }
+
+    fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn pop_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn lookup(&self, name: &str) -> Option<BindingId> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).copied())
+            .or_else(|| self.globals.get(name).copied())
+    }
+
+    fn add_symbol(&mut self, name: &Name, kind: SymbolKind) -> BindingId {
+        let id = BindingId(self.symbols.symbols.len());
+        self.symbols.symbols.push(Symbol {
+            name: name.name.clone(),
+            kind,
+            span: name.span,
+            used: false,
+        });
+        self.symbols.resolutions.insert(name.span, id);
+        id
+    }
+
+    fn declare(&mut self, name: &Name, kind: SymbolKind) {
+        // parameters sharing a name are reported as duplicates instead
+        if kind == SymbolKind::Local && self.lookup(&name.name).is_some() {
+            self.diagnostics.push(Diagnostic::warning(
+                format!("`{}` shadows an earlier binding", name.name),
+                name.span,
+            ));
+        }
+
+        let id = self.add_symbol(name, kind);
+
+        self.scopes
+            .last_mut()
+            .expect("no scope to declare variable in")
+            .insert(name.name.clone(), id);
+    }
+
+    fn declare_global(&mut self, name: &Name, kind: SymbolKind) {
+        if self.globals.contains_key(&name.name) {
+            self.error(CompileError::DuplicateGlobal {
+                name: name.name.clone(),
+                span: name.span,
+            });
+            return;
+        }
+
+        let id = self.add_symbol(name, kind);
+        self.globals.insert(name.name.clone(), id);
+    }
+
+    fn declare_function(&mut self, name: &Name, params: usize) {
+        if self.symbols.functions.contains_key(&name.name) {
+            self.error(CompileError::DuplicateFunction {
+                name: name.name.clone(),
+                span: name.span,
+            });
+        } else {
+            let symbol = FunctionSymbol {
+                params,
+                span: name.span,
+            };
+            self.symbols.functions.insert(name.name.clone(), symbol);
+        }
+    }
+
+    fn resolve_use(&mut self, name: &Name, read: bool) {
+        match self.lookup(&name.name) {
+            Some(id) => {
+                self.symbols.resolutions.insert(name.span, id);
+                if read {
+                    self.symbols.symbols[id.0].used = true;
+                }
+            }
+            None => self.error(CompileError::UnknownVariable {
+                name: name.name.clone(),
+                span: name.span,
+            }),
+        }
+    }
+
+    fn resolve_expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::IntLiteral(..)
+            | ExprKind::FloatLiteral(_)
+            | ExprKind::BoolLiteral(_)
+            | ExprKind::CharLiteral(_)
+            | ExprKind::StringLiteral(_)
+            | ExprKind::Null => {}
+            ExprKind::Name(name) => self.resolve_use(name, true),
+            // taking the address counts as reading the variable, it may be
+            // read through the pointer
+            ExprKind::Unary(_, operand)
+            | ExprKind::AddressOf(operand)
+            | ExprKind::Deref(operand)
+            | ExprKind::Cast(operand, _) => self.resolve_expr(operand),
+            ExprKind::Binary(_, lhs, rhs) | ExprKind::Logical(_, lhs, rhs) => {
+                self.resolve_expr(lhs);
+                self.resolve_expr(rhs);
+            }
+            ExprKind::Call(name, args) => {
+                if self.symbols.function(&name.name).is_none()
+                    && Builtin::from_name(&name.name).is_none()
+                {
+                    self.error(CompileError::UnknownFunction {
+                        name: name.name.clone(),
+                        span: name.span,
+                    });
+                }
+
+                for arg in args {
+                    self.resolve_expr(arg);
+                }
+            }
+            // field names are checked along with the types
+            ExprKind::StructLiteral(_, fields) => {
+                for (_, value) in fields {
+                    self.resolve_expr(value);
+                }
+            }
+            ExprKind::Field(base, _) => self.resolve_expr(base),
+            // enum and variant names are checked along with the types
+            ExprKind::Variant(_, _, values) => {
+                for value in values {
+                    self.resolve_expr(value);
+                }
+            }
+            ExprKind::ArrayLiteral(values) => {
+                for value in values {
+                    self.resolve_expr(value);
+                }
+            }
+            ExprKind::Index(base, index) => {
+                self.resolve_expr(base);
+                self.resolve_expr(index);
+            }
+            ExprKind::Match(scrutinee, arms) => {
+                self.resolve_expr(scrutinee);
+
+                for arm in arms {
+                    self.resolve_arm(arm);
+                }
+            }
+        }
+    }
+
+    /// Resolves an arm of a `match`, its bindings are only visible in the
+    /// arm itself.
+    fn resolve_arm(&mut self, arm: &Arm) {
+        self.push_scope();
+
+        let mut bound = Vec::new();
+        self.declare_pattern(&arm.pattern, &mut bound);
+
+        for stmt in &arm.body {
+            self.resolve_stmt(stmt);
+        }
+
+        if let Some(value) = &arm.value {
+            self.resolve_expr(value);
+        }
+
+        self.pop_scope();
+    }
+
+    fn declare_pattern<'p>(&mut self, pattern: &'p Pattern, bound: &mut Vec<&'p str>) {
+        match &pattern.kind {
+            PatternKind::Wildcard | PatternKind::Literal(_) => {}
+            PatternKind::Binding(name) => {
+                if bound.contains(&name.name.as_str()) {
+                    self.error(CompileError::DuplicateBinding {
+                        name: name.name.clone(),
+                        span: name.span,
+                    });
+                } else {
+                    bound.push(&name.name);
+                    self.declare(name, SymbolKind::Local);
+                }
+            }
+            PatternKind::Variant(_, _, fields) => {
+                for field in fields {
+                    self.declare_pattern(field, bound);
+                }
+            }
+        }
+    }
+
+    /// Resolves the target of an assignment. Storing to a variable does not
+    /// count as reading it, but storing to one of its fields or elements
+    /// does, as the variable may be a pointer or a slice to memory stored
+    /// elsewhere. Constants and statics without `mut` cannot be assigned to.
+    fn resolve_place(&mut self, place: &Expr) {
+        match &place.kind {
+            ExprKind::Name(name) => {
+                self.resolve_use(name, false);
+
+                let kind = self
+                    .symbols
+                    .binding(name)
+                    .map(|id| self.symbols.symbol(id).kind);
+                if let Some(SymbolKind::Constant | SymbolKind::Static { mutable: false }) = kind {
+                    self.error(CompileError::ImmutableAssignment {
+                        name: name.name.clone(),
+                        span: name.span,
+                    });
+                }
+            }
+            ExprKind::Field(base, _) => {
+                self.resolve_place(base);
+                self.mark_read(base);
+            }
+            ExprKind::Index(base, index) => {
+                self.resolve_place(base);
+                self.mark_read(base);
+                self.resolve_expr(index);
+            }
+            _ => self.resolve_expr(place),
+        }
+    }
+
+    /// Marks the variable a resolved place is part of as read.
+    fn mark_read(&mut self, place: &Expr) {
+        match &place.kind {
+            ExprKind::Name(name) => {
+                if let Some(id) = self.symbols.binding(name) {
+                    self.symbols.symbols[id.0].used = true;
+                }
+            }
+            ExprKind::Field(base, _) | ExprKind::Index(base, _) => self.mark_read(base),
+            _ => {}
+        }
+    }
+
+    fn resolve_block(&mut self, block: &[Stmt]) {
+        self.push_scope();
+        for stmt in block {
+            self.resolve_stmt(stmt);
+        }
+        self.pop_scope();
+    }
+
+    fn resolve_loop_body(&mut self, body: &[Stmt]) {
+        self.loop_depth += 1;
+        self.resolve_block(body);
+        self.loop_depth -= 1;
+    }
+
+    fn resolve_stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Expr(expr) | StmtKind::Return(Some(expr)) => self.resolve_expr(expr),
+            StmtKind::Return(None) => {}
+            StmtKind::Let(name, _, expr) => {
+                // the initializer is resolved before the binding is visible,
+                // so `let x = x + 1` reads the shadowed `x`
+                self.resolve_expr(expr);
+                self.declare(name, SymbolKind::Local);
+            }
+            StmtKind::Assign(place, expr) => {
+                self.resolve_expr(expr);
+                self.resolve_place(place);
+            }
+            StmtKind::If(cond, then_b, else_b) => {
+                self.resolve_expr(cond);
+                self.resolve_block(then_b);
+                if let Some(else_b) = else_b {
+                    self.resolve_block(else_b);
+                }
+            }
+            StmtKind::For(init, cond, step, body) => {
+                self.push_scope();
+                self.resolve_stmt(init);
+                self.resolve_expr(cond);
+                self.resolve_loop_body(body);
+                self.resolve_stmt(step);
+                self.pop_scope();
+            }
+            StmtKind::While(cond, body) => {
+                self.resolve_expr(cond);
+                self.resolve_loop_body(body);
+            }
+            StmtKind::Loop(body) => self.resolve_loop_body(body),
+            StmtKind::Break | StmtKind::Continue if self.loop_depth == 0 => {
+                let keyword = match stmt.kind {
+                    StmtKind::Break => "break",
+                    _ => "continue",
+                };
+                self.error(CompileError::LoopControlOutsideLoop {
+                    keyword: keyword.to_string(),
+                    span: stmt.span,
+                });
+            }
+            StmtKind::Break | StmtKind::Continue => {}
+        }
+    }
+
+    /// Reports a struct or enum sharing its name with another type.
+    fn check_type_name(&mut self, name: &Name, earlier: &[&Name]) {
+        let declared = earlier.iter().any(|other| other.name == name.name);
+
+        if declared || Type::from_name(&name.name).is_some() {
+            self.error(CompileError::DuplicateType {
+                name: name.name.clone(),
+                span: name.span,
+            });
+        }
+    }
+
+    /// Reports fields of a struct declared twice.
+    fn check_struct(&mut self, def: &Struct) {
+        for (i, field) in def.fields.iter().enumerate() {
+            let field = &field.name;
+
+            if def.fields[..i]
+                .iter()
+                .any(|other| other.name.name == field.name)
+            {
+                self.error(CompileError::DuplicateField {
+                    name: field.name.clone(),
+                    span: field.span,
+                });
+            }
+        }
+    }
+
+    /// Reports variants of an enum declared twice.
+    fn check_enum(&mut self, def: &Enum) {
+        for (i, variant) in def.variants.iter().enumerate() {
+            let variant = &variant.name;
+
+            if def.variants[..i]
+                .iter()
+                .any(|other| other.name.name == variant.name)
+            {
+                self.error(CompileError::DuplicateVariant {
+                    name: variant.name.clone(),
+                    span: variant.span,
+                });
+            }
+        }
+    }
+
+    /// Resolves the initializer of a constant or a static, which only sees
+    /// other globals.
+    fn resolve_initializer(&mut self, value: &Expr) {
+        self.scopes.clear();
+        self.resolve_expr(value);
+    }
+
+    /// Resolves the parameters and the body of a function. If the parser left
+    /// out a statement of the body, nothing is reported from there on, as the
+    /// statement may have declared or used any of the names.
+    fn resolve_function(&mut self, function: &Function) {
+        self.scopes.clear();
+        self.push_scope();
+
+        let first_symbol = self.symbols.symbols.len();
+
+        for (i, arg) in function.args.iter().enumerate() {
+            let arg = &arg.name;
+
+            if function.args[..i]
+                .iter()
+                .any(|other| other.name.name == arg.name)
+            {
+                self.error(CompileError::DuplicateParameter {
+                    name: arg.name.clone(),
+                    span: arg.span,
+                });
+            }
+
+            self.declare(arg, SymbolKind::Parameter);
+        }
+
+        let reported = self.diagnostics.len();
+        self.resolve_block(&function.body);
+        self.pop_scope();
+
+        if let Some(skipped) = function.skipped {
+            let before: Vec<_> = self
+                .diagnostics
+                .drain(reported..)
+                .filter(|diagnostic| diagnostic.span.end <= skipped.start)
+                .collect();
+            self.diagnostics.extend(before);
+
+            for symbol in &mut self.symbols.symbols[first_symbol..] {
+                symbol.used = true;
+            }
+        }
+    }
+}
+
+/// Resolves every name of the program before it is lowered, reporting
+/// undefined names as errors and shadowed or unused bindings as warnings.
+pub fn resolve_program(program: &Program) -> Resolution {
+    let mut resolver = Resolver {
+        symbols: SymbolTable::default(),
+        scopes: Vec::new(),
+        globals: HashMap::new(),
+        loop_depth: 0,
+        diagnostics: Vec::new(),
+    };
+
+    let type_names = program
+        .structs
+        .iter()
+        .map(|def| &def.name)
+        .chain(program.enums.iter().map(|def| &def.name));
+    let mut declared = Vec::new();
+
+    for name in type_names {
+        resolver.check_type_name(name, &declared);
+        declared.push(name);
+    }
+
+    for def in &program.structs {
+        resolver.check_struct(def);
+    }
+
+    for def in &program.enums {
+        resolver.check_enum(def);
+    }
+
+    for function in &program.externs {
+        resolver.declare_function(&function.name, function.args.len());
+    }
+
+    for function in &program.functions {
+        resolver.declare_function(&function.name, function.args.len());
+    }
+
+    // globals are declared before any initializer or body is resolved, so
+    // they can be used before the line declaring them
+    for def in &program.consts {
+        resolver.declare_global(&def.name, SymbolKind::Constant);
+    }
+
+    for def in &program.statics {
+        let kind = SymbolKind::Static {
+            mutable: def.mutable,
+        };
+        resolver.declare_global(&def.name, kind);
+    }
+
+    let initializers = program
+        .consts
+        .iter()
+        .map(|def| &def.value)
+        .chain(program.statics.iter().map(|def| &def.value));
+
+    for value in initializers {
+        resolver.resolve_initializer(value);
+    }
+
+    for function in &program.functions {
+        resolver.resolve_function(function);
+    }
+
+    let recovered = program
+        .functions
+        .iter()
+        .any(|function| function.skipped.is_some());
+
+    for symbol in &resolver.symbols.symbols {
+        // globals may only be used by statements the parser left out
+        let global = matches!(
+            symbol.kind,
+            SymbolKind::Constant | SymbolKind::Static { .. }
+        );
+        let used = symbol.used || (global && recovered);
+
+        if !used && !symbol.name.starts_with('_') {
+            let what = match symbol.kind {
+                SymbolKind::Parameter | SymbolKind::Local => "variable",
+                SymbolKind::Constant => "constant",
+                SymbolKind::Static { .. } => "static",
+            };
+            resolver.diagnostics.push(Diagnostic::warning(
+                format!("unused {} `{}`", what, symbol.name),
+                symbol.span,
+            ));
+        }
+    }
+
+    resolver
+        .diagnostics
+        .sort_by_key(|diagnostic| diagnostic.span.start);
+
+    Resolution {
+        symbols: resolver.symbols,
+        diagnostics: resolver.diagnostics,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_source;
+
+    /// Messages of the diagnostics the resolver reports for the program.
+    fn diagnostics(code: &str) -> Vec<String> {
+        let (program, _) = parse_source(code);
+        resolve_program(&program)
+            .diagnostics
+            .into_iter()
+            .map(|diagnostic| diagnostic.message)
+            .collect()
+    }
+
+    #[test]
+    fn reports_before_a_skipped_statement() {
+        let code = "fun main() {
+            let a = b;
+            let = 5;
+            let c = d;
+            println(a);
+        }";
+
+        let (_, syntax_errors) = parse_source(code);
+        assert_eq!(syntax_errors.len(), 1);
+        assert_eq!(diagnostics(code), vec!["unknown variable `b`"]);
+    }
+
+    #[test]
+    fn storing_to_an_element_reads_the_array() {
+        let code = "fun main() {
+            let values = [1, 2, 3];
+            values[0] = 4;
+        }";
+
+        assert_eq!(diagnostics(code), Vec::<String>::new());
+    }
+
+    #[test]
+    fn storing_to_a_field_reads_the_pointer() {
+        let code = "struct Point { x: i64 }
+
+        fun reset(point: *Point) {
+            point.x = 0;
+        }";
+
+        assert_eq!(diagnostics(code), Vec::<String>::new());
+    }
+}
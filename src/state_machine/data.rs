@@ -1,8 +1,8 @@
-use crate::internal_representations::gast::Program;
+use crate::{diagnostics::SourceFile, internal_representations::gast::Program};
 
 pub struct CompilerData {
     pub(super) config: Config,
-    pub(super) code: Option<String>,
+    pub(super) source: Option<SourceFile>,
     pub(super) ast: Option<Program>,
 }
 
@@ -10,7 +10,7 @@ impl CompilerData {
     pub fn new(config: Config) -> Self {
         CompilerData {
             config,
-            code: None,
+            source: None,
             ast: None,
         }
     }
@@ -18,16 +18,24 @@ impl CompilerData {
 
 pub struct Config {
     pub project_directory: String,
+    /// Whether indexing is checked at runtime, `--no-bounds-checks` turns
+    /// the checks off.
+    pub bounds_checks: bool,
 }
 
 impl Config {
-    pub fn from_args(_args: Vec<String>) -> anyhow::Result<Self> {
+    pub fn from_args(args: Vec<String>) -> anyhow::Result<Self> {
         let project_directory = std::env::current_dir()?
             .to_str()
             .ok_or_else(|| anyhow::anyhow!("Could not convert path to string"))?
             .to_string();
 
+        let bounds_checks = !args.iter().any(|arg| arg == "--no-bounds-checks");
+
         println!("Project directory: {}", project_directory);
-        Ok(Config { project_directory })
+        Ok(Config {
+            project_directory,
+            bounds_checks,
+        })
     }
 }
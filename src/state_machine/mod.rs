@@ -7,10 +7,12 @@ use inkwell::{
     targets::{CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine},
     OptimizationLevel,
 };
-use pest::Parser;
 use walkdir::WalkDir;
 
-use crate::{code_generator::Compiler, parser};
+use crate::{
+    code_generator::Compiler, diagnostics::SourceFile, error::CompileError, lowering, parser,
+    resolver,
+};
 
 use self::{
     data::{CompilerData, Config},
@@ -101,13 +103,24 @@ impl CompilerMachine {
         Ok(tree)
     }
 
+    fn source(&self) -> &SourceFile {
+        self.data.source.as_ref().unwrap()
+    }
+
+    fn report(&self, error: CompileError) -> anyhow::Error {
+        anyhow::anyhow!(error.render(self.source()))
+    }
+
     fn parse(&mut self) -> anyhow::Result<()> {
-        let raw_ast =
-            parser::Parser::parse(parser::Rule::program, self.data.code.as_ref().unwrap())
-                .unwrap()
-                .next()
-                .unwrap();
-        self.data.ast = Some(parser::parse_program(raw_ast).unwrap());
+        let (program, errors) = parser::parse_source(&self.source().code);
+        self.data.ast = Some(program);
+
+        for error in &errors {
+            eprintln!("{}", error.render(self.source()));
+        }
+        if !errors.is_empty() {
+            bail!("could not compile due to previous errors");
+        }
 
         Ok(())
     }
@@ -152,14 +165,29 @@ impl CompilerMachine {
 
         fpm.initialize();
 
-        let function: inkwell::values::FunctionValue = Compiler::compile(
+        let ast = self.data.ast.as_ref().unwrap();
+
+        let resolution = resolver::resolve_program(ast);
+        for diagnostic in &resolution.diagnostics {
+            eprintln!("{}", diagnostic.render(self.source()));
+        }
+        if resolution.has_errors() {
+            bail!("could not compile due to previous errors");
+        }
+
+        let program = lowering::lower_program(ast, &resolution.symbols)
+            .map_err(|error| self.report(error))?;
+
+        Compiler::compile(
             &context,
             &builder,
             &fpm,
             &module,
-            self.data.ast.as_ref().unwrap(),
+            &program,
+            self.source(),
+            self.data.config.bounds_checks,
         )
-        .unwrap();
+        .map_err(|error| self.report(error))?;
 
         Ok(())
     }